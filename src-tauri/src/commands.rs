@@ -1,14 +1,27 @@
 use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
 use base64::Engine;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use git2::{Oid, Repository, Signature, Status, StatusOptions};
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::{CompressionType, FilterType as PngFilterType, PngEncoder};
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageDecoder, ImageReader, Rgb, RgbImage};
+use quick_xml::events::{BytesEnd, BytesStart, BytesText, Event as XmlEvent};
+use quick_xml::{Reader as XmlReader, Writer as XmlWriter};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use similar::{ChangeTag, TextDiff};
 use std::collections::{hash_map::DefaultHasher, HashMap, HashSet};
-use std::fs::{self, OpenOptions};
+use std::fs::{self, File, OpenOptions};
 use std::hash::{Hash, Hasher};
-use std::io::{ErrorKind, Write};
-use std::net::{TcpStream, ToSocketAddrs};
+use std::io::{Cursor, ErrorKind, Read, Write};
 use std::path::{Path, PathBuf};
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
@@ -60,6 +73,30 @@ pub struct MarkdownFileEntry {
     pub relative_path: String,
 }
 
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageInfo {
+    pub line: u32,
+    pub link: String,
+    pub path: String,
+    pub exists: bool,
+    pub format: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub size_bytes: u64,
+    pub thumbnail: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrphanedAsset {
+    pub path: String,
+    pub relative_path: String,
+    pub size_bytes: u64,
+    pub modified_ms: u64,
+}
+
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SearchHit {
@@ -75,6 +112,23 @@ pub struct SearchHit {
 pub struct SavedImageAsset {
     pub path: String,
     pub relative_path: String,
+    pub reused: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedAttachment {
+    pub path: String,
+    pub relative_path: String,
+    pub reused: bool,
+    pub size_bytes: u64,
+    pub markdown: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct AssetManifest {
+    entries: HashMap<String, String>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -84,15 +138,133 @@ pub struct SnapshotEntry {
     pub created_at_ms: u64,
     pub reason: String,
     pub size_bytes: u64,
+    pub pinned: bool,
+    pub name: Option<String>,
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffSegment {
+    pub kind: String,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffLine {
+    pub kind: String,
+    pub old_line: Option<usize>,
+    pub new_line: Option<usize>,
+    pub text: String,
+    pub segments: Vec<DiffSegment>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffHunk {
+    pub old_start: usize,
+    pub old_lines: usize,
+    pub new_start: usize,
+    pub new_lines: usize,
+    pub lines: Vec<DiffLine>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffStats {
+    pub lines_added: usize,
+    pub lines_removed: usize,
+    pub words_added: usize,
+    pub words_removed: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotDiff {
+    pub hunks: Vec<DiffHunk>,
+    pub stats: DiffStats,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GitFileStatus {
+    pub path: String,
+    pub relative_path: String,
+    pub status: String,
+    pub staged: bool,
+    pub unstaged: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GitStatusSummary {
+    pub repository_root: String,
+    pub branch: Option<String>,
+    pub files: Vec<GitFileStatus>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GitCommitInfo {
+    pub id: String,
+    pub short_id: String,
+    pub summary: String,
+    pub author: String,
+    pub email: String,
+    pub time_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GitBlameLine {
+    pub line: u32,
+    pub commit: Option<GitCommitInfo>,
+    pub uncommitted: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GitRevisionContent {
+    pub path: String,
+    pub commit: GitCommitInfo,
+    pub content: String,
 }
 
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct LinkValidationIssue {
     pub line: u32,
+    pub column: Option<usize>,
     pub link: String,
     pub severity: String,
     pub message: String,
+    pub status_code: Option<u16>,
+    pub redirect_target: Option<String>,
+    pub suggestions: Vec<LinkFixSuggestion>,
+}
+
+impl LinkValidationIssue {
+    fn new(line: u32, link: impl Into<String>, severity: &str, message: impl Into<String>) -> Self {
+        Self {
+            line,
+            column: None,
+            link: link.into(),
+            severity: severity.to_string(),
+            message: message.into(),
+            status_code: None,
+            redirect_target: None,
+            suggestions: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkFixSuggestion {
+    pub kind: String,
+    pub replacement: String,
+    pub description: String,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -102,6 +274,207 @@ pub struct LinkValidationReport {
     pub issues: Vec<LinkValidationIssue>,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ExternalCheckOptions {
+    pub concurrency: u32,
+    pub per_host_interval_ms: u64,
+    pub cache_ttl_ms: u64,
+    pub timeout_ms: u64,
+}
+
+impl Default for ExternalCheckOptions {
+    fn default() -> Self {
+        Self {
+            concurrency: 8,
+            per_host_interval_ms: 250,
+            cache_ttl_ms: 24 * 60 * 60 * 1000,
+            timeout_ms: 8_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BacklinkEntry {
+    pub path: String,
+    pub relative_path: String,
+    pub line: u32,
+    pub link: String,
+    pub context: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutgoingLinkEntry {
+    pub line: u32,
+    pub link: String,
+    pub kind: String,
+    pub target_path: Option<String>,
+    pub exists: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkGraphNode {
+    pub id: String,
+    pub path: String,
+    pub name: String,
+    pub inbound: u32,
+    pub outbound: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkGraphEdge {
+    pub source: String,
+    pub target: String,
+    pub count: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkGraph {
+    pub nodes: Vec<LinkGraphNode>,
+    pub edges: Vec<LinkGraphEdge>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkRewrite {
+    pub path: String,
+    pub relative_path: String,
+    pub line: u32,
+    pub old_link: String,
+    pub new_link: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentRenamePlan {
+    pub old_path: String,
+    pub new_path: String,
+    pub rewrites: Vec<LinkRewrite>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HeadingRenamePlan {
+    pub old_slug: String,
+    pub new_slug: String,
+    pub rewrites: Vec<LinkRewrite>,
+    pub updated_markdown: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HistoryRetentionSettings {
+    pub keep_all_for_ms: u64,
+    pub hourly_for_ms: u64,
+    pub daily_for_ms: u64,
+    pub weekly_for_ms: Option<u64>,
+    pub max_snapshots_per_file: Option<u32>,
+    pub autosave_coalesce_ms: u64,
+}
+
+impl Default for HistoryRetentionSettings {
+    fn default() -> Self {
+        Self {
+            keep_all_for_ms: HOUR_MS,
+            hourly_for_ms: DAY_MS,
+            daily_for_ms: 30 * DAY_MS,
+            weekly_for_ms: None,
+            max_snapshots_per_file: None,
+            autosave_coalesce_ms: 60_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HistoryStorageSettings {
+    pub quota_bytes: Option<u64>,
+    pub delta_encoding: bool,
+}
+
+impl Default for HistoryStorageSettings {
+    fn default() -> Self {
+        Self {
+            quota_bytes: None,
+            delta_encoding: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AssetSettings {
+    pub relative_to: String,
+    pub directory: String,
+    pub file_name: String,
+}
+
+impl Default for AssetSettings {
+    fn default() -> Self {
+        Self {
+            relative_to: "document".to_string(),
+            directory: "assets".to_string(),
+            file_name: "{name}-{timestamp}".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ImageProcessingSettings {
+    pub enabled: bool,
+    pub max_dimension: Option<u32>,
+    pub format: String,
+    // Not used for WebP, which is always encoded losslessly.
+    pub quality: u8,
+    pub strip_metadata: bool,
+}
+
+impl Default for ImageProcessingSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_dimension: None,
+            format: "keep".to_string(),
+            quality: 85,
+            strip_metadata: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AttachmentSettings {
+    pub allowed_extensions: Vec<String>,
+    pub max_size_bytes: u64,
+}
+
+impl Default for AttachmentSettings {
+    fn default() -> Self {
+        Self {
+            allowed_extensions: ["pdf", "csv", "tsv", "txt", "json", "xlsx", "xls", "docx", "pptx", "zip"]
+                .iter()
+                .map(|extension| extension.to_string())
+                .collect(),
+            max_size_bytes: 25 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct WorkspaceSettings {
+    pub history: HistoryRetentionSettings,
+    pub assets: AssetSettings,
+    pub images: ImageProcessingSettings,
+    pub attachments: AttachmentSettings,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SessionStateDto {
@@ -140,13 +513,174 @@ struct SnapshotRecord {
     created_at_ms: u64,
     reason: String,
     size_bytes: u64,
+    #[serde(default)]
     file_path: String,
     content_hash: u64,
+    #[serde(default)]
+    pinned: bool,
+    #[serde(default)]
+    blob: Option<String>,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    notes: Option<String>,
+    #[serde(default)]
+    word_count: Option<u64>,
+    #[serde(default)]
+    words_added: Option<u64>,
+    #[serde(default)]
+    words_removed: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BlobInfo {
+    base: Option<String>,
+    depth: u32,
+    stored_bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct SettingsStore {
+    history_storage: HistoryStorageSettings,
+    workspaces: HashMap<String, WorkspaceSettings>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 struct HistoryIndex {
     files: HashMap<String, Vec<SnapshotRecord>>,
+    #[serde(default)]
+    blobs: HashMap<String, BlobInfo>,
+    #[serde(skip)]
+    persisted: HashMap<String, String>,
+    #[serde(skip)]
+    persisted_blobs: Option<String>,
+    #[serde(skip)]
+    persisted_records: HashMap<PathBuf, String>,
+    #[serde(skip)]
+    pending_removals: Vec<PathBuf>,
+    #[serde(skip)]
+    quarantined: usize,
+    #[serde(skip)]
+    recovered: usize,
+    #[serde(skip)]
+    unrecovered: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HistoryArchive {
+    version: u32,
+    path: String,
+    records: Vec<SnapshotRecord>,
+    contents: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DocumentHistory {
+    path: String,
+    records: Vec<SnapshotRecord>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SnapshotMetadata {
+    path: String,
+    record: SnapshotRecord,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotSearchHit {
+    pub path: String,
+    pub snapshot_id: String,
+    pub created_at_ms: u64,
+    pub name: Option<String>,
+    pub line: u32,
+    pub snippet: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryCandidate {
+    pub path: String,
+    pub snapshot_count: usize,
+    pub last_snapshot_ms: u64,
+    pub matches_content: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActivityFileSummary {
+    pub path: String,
+    pub edits: usize,
+    pub words_added: u64,
+    pub words_removed: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActivityBucket {
+    pub start_ms: u64,
+    pub edits: usize,
+    pub words_added: u64,
+    pub words_removed: u64,
+    pub files: Vec<ActivityFileSummary>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActivityTimeline {
+    pub buckets: Vec<ActivityBucket>,
+    pub files: Vec<ActivityFileSummary>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ActivityQuery {
+    pub workspace_folder: Option<String>,
+    pub from_ms: Option<u64>,
+    pub to_ms: Option<u64>,
+    pub granularity: String,
+    pub utc_offset_minutes: i64,
+}
+
+impl Default for ActivityQuery {
+    fn default() -> Self {
+        Self {
+            workspace_folder: None,
+            from_ms: None,
+            to_ms: None,
+            granularity: "day".to_string(),
+            utc_offset_minutes: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryRepairReport {
+    pub dropped_records: usize,
+    pub removed_blobs: usize,
+    pub removed_files: usize,
+    pub quarantined_indexes: usize,
+    pub recovered_records: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExternalLinkStatus {
+    status_code: Option<u16>,
+    final_url: Option<String>,
+    error: Option<String>,
+    checked_at_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+struct ExternalLinkCache {
+    entries: HashMap<String, ExternalLinkStatus>,
 }
 
 fn map_io_error(error: &std::io::Error) -> AppError {
@@ -255,8 +789,16 @@ fn history_dir() -> Result<PathBuf, AppError> {
     Ok(app_support_dir()?.join("history"))
 }
 
-fn history_index_path() -> Result<PathBuf, AppError> {
-    Ok(history_dir()?.join("index.json"))
+fn thumbnail_cache_dir() -> Result<PathBuf, AppError> {
+    Ok(app_support_dir()?.join("thumbnails"))
+}
+
+fn external_link_cache_path() -> Result<PathBuf, AppError> {
+    Ok(app_support_dir()?.join("link-check-cache.json"))
+}
+
+fn settings_store_path() -> Result<PathBuf, AppError> {
+    Ok(app_support_dir()?.join("settings.json"))
 }
 
 fn session_state_path() -> Result<PathBuf, AppError> {
@@ -457,408 +999,4253 @@ fn sanitize_stem(name: &str) -> String {
     }
 }
 
-fn ext_from_mime(mime_type: &str) -> Option<&'static str> {
-    let lower = mime_type.to_ascii_lowercase();
-    if lower.contains("png") {
-        Some("png")
-    } else if lower.contains("jpeg") || lower.contains("jpg") {
-        Some("jpg")
-    } else if lower.contains("gif") {
-        Some("gif")
-    } else if lower.contains("webp") {
-        Some("webp")
-    } else if lower.contains("bmp") {
-        Some("bmp")
-    } else if lower.contains("svg") {
-        Some("svg")
-    } else {
-        None
-    }
-}
-
 fn ext_from_path(path: &Path) -> Option<String> {
     path.extension()
         .and_then(|value| value.to_str())
         .map(|value| value.to_ascii_lowercase())
 }
 
-fn next_asset_path(document_path: &Path, preferred_name: &str, extension: &str) -> Result<PathBuf, AppError> {
-    let parent = document_path
-        .parent()
-        .ok_or_else(|| AppError::new(AppErrorCode::Io, "Document path has no parent"))?;
+const ASSET_MANIFEST_NAME: &str = ".asset-manifest.json";
 
-    let assets_dir = parent.join("assets");
-    fs::create_dir_all(&assets_dir).map_err(|error| map_io_error(&error))?;
+fn next_asset_path(assets_dir: &Path, stem: &str, extension: &str) -> Result<PathBuf, AppError> {
+    fs::create_dir_all(assets_dir).map_err(|error| map_io_error(&error))?;
 
-    let now = now_ms()?;
-    let stem = sanitize_stem(preferred_name);
-    let mut candidate = assets_dir.join(format!("{}-{}.{}", stem, now, extension));
+    let mut candidate = assets_dir.join(format!("{}.{}", stem, extension));
     let mut counter = 1u32;
     while candidate.exists() {
-        candidate = assets_dir.join(format!("{}-{}-{}.{}", stem, now, counter, extension));
+        candidate = assets_dir.join(format!("{}-{}.{}", stem, counter, extension));
         counter += 1;
     }
 
     Ok(candidate)
 }
 
-fn save_asset_bytes(
-    document_path: &Path,
-    preferred_name: &str,
+fn utc_date(timestamp_ms: u64) -> String {
+    let days = (timestamp_ms / DAY_MS) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+fn expand_asset_template(template: &str, document_path: &Path, preferred_name: &str, now: u64) -> String {
+    let document_name = document_path
+        .file_stem()
+        .and_then(|value| value.to_str())
+        .unwrap_or("document");
+    template
+        .replace("{doc}", &sanitize_stem(document_name))
+        .replace("{name}", &sanitize_stem(preferred_name))
+        .replace("{date}", &utc_date(now))
+        .replace("{timestamp}", &now.to_string())
+}
+
+fn asset_directory(
+    document_path: &Path,
+    settings: &AssetSettings,
+    workspace_root: Option<&Path>,
+    now: u64,
+) -> Result<PathBuf, AppError> {
+    let document_dir = document_path
+        .parent()
+        .ok_or_else(|| AppError::new(AppErrorCode::Io, "Document path has no parent"))?;
+    let base = match workspace_root {
+        Some(root) if settings.relative_to == "workspace" => root,
+        _ => document_dir,
+    };
+
+    let directory = expand_asset_template(&settings.directory, document_path, "image", now);
+    if Path::new(&directory).is_absolute() {
+        return Err(AppError::new(
+            AppErrorCode::Io,
+            "Asset directory must be relative",
+        ));
+    }
+    let resolved = normalize_lexical_path(&base.join(directory));
+    if !resolved.starts_with(normalize_lexical_path(workspace_root.unwrap_or(document_dir))) {
+        return Err(AppError::new(
+            AppErrorCode::Io,
+            "Asset directory must stay inside the workspace",
+        ));
+    }
+    Ok(resolved)
+}
+
+fn is_asset_location(relative: &Path, settings: &AssetSettings) -> bool {
+    let Some(parent) = relative.parent() else {
+        return false;
+    };
+    if parent.components().any(|component| component.as_os_str() == "assets") {
+        return true;
+    }
+
+    let prefix: PathBuf = Path::new(&settings.directory)
+        .components()
+        .take_while(|component| !component.as_os_str().to_string_lossy().contains('{'))
+        .filter(|component| matches!(component, std::path::Component::Normal(_)))
+        .collect();
+    if prefix.as_os_str().is_empty() {
+        return false;
+    }
+    if settings.relative_to == "workspace" {
+        parent.starts_with(&prefix)
+    } else {
+        parent
+            .ancestors()
+            .any(|ancestor| !ancestor.as_os_str().is_empty() && ancestor.ends_with(&prefix))
+    }
+}
+
+fn load_asset_manifest(assets_dir: &Path) -> AssetManifest {
+    let manifest_path = assets_dir.join(ASSET_MANIFEST_NAME);
+    if let Some(manifest) = fs::read(&manifest_path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice::<AssetManifest>(&bytes).ok())
+    {
+        return manifest;
+    }
+
+    let mut manifest = AssetManifest::default();
+    if let Ok(entries) = fs::read_dir(assets_dir) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Some(name) = path.file_name().and_then(|value| value.to_str()) else {
+                continue;
+            };
+            if name.starts_with('.') || !path.is_file() {
+                continue;
+            }
+            if let Ok(bytes) = fs::read(&path) {
+                manifest.entries.insert(content_digest(&bytes), name.to_string());
+            }
+        }
+    }
+    manifest
+}
+
+fn find_duplicate_asset(assets_dir: &Path, manifest: &mut AssetManifest, digest: &str) -> Option<PathBuf> {
+    let name = manifest.entries.get(digest)?;
+    let candidate = assets_dir.join(name);
+    let unchanged = fs::read(&candidate)
+        .map(|bytes| content_digest(&bytes) == digest)
+        .unwrap_or(false);
+    if unchanged {
+        Some(candidate)
+    } else {
+        manifest.entries.remove(digest);
+        None
+    }
+}
+
+fn save_asset_bytes(
+    document_path: &Path,
+    preferred_name: &str,
     extension: &str,
     bytes: &[u8],
+    settings: &AssetSettings,
+    workspace_root: Option<&Path>,
 ) -> Result<SavedImageAsset, AppError> {
-    let destination = next_asset_path(document_path, preferred_name, extension)?;
-    atomic_write_bytes(&destination, bytes)?;
-
     let parent = document_path
         .parent()
         .ok_or_else(|| AppError::new(AppErrorCode::Io, "Document path has no parent"))?;
-    let relative_path = destination
-        .strip_prefix(parent)
-        .unwrap_or(&destination)
-        .to_string_lossy()
-        .to_string();
+    let now = now_ms()?;
+    let assets_dir = asset_directory(document_path, settings, workspace_root, now)?;
+
+    let digest = content_digest(bytes);
+    let mut manifest = load_asset_manifest(&assets_dir);
+    let (destination, reused) = match find_duplicate_asset(&assets_dir, &mut manifest, &digest) {
+        Some(existing) => (existing, true),
+        None => {
+            let stem = sanitize_stem(&expand_asset_template(&settings.file_name, document_path, preferred_name, now));
+            let destination = next_asset_path(&assets_dir, &stem, extension)?;
+            atomic_write_bytes(&destination, bytes)?;
+            (destination, false)
+        }
+    };
+
+    if let Some(name) = destination.file_name().and_then(|value| value.to_str()) {
+        manifest.entries.insert(digest, name.to_string());
+    }
+    let serialized = serde_json::to_string_pretty(&manifest)
+        .map_err(|error| AppError::new(AppErrorCode::Io, error.to_string()))?;
+    atomic_write(&assets_dir.join(ASSET_MANIFEST_NAME), &serialized)?;
+
+    let relative_path = relative_link_path(&normalize_lexical_path(parent), &destination);
 
     Ok(SavedImageAsset {
         path: destination.to_string_lossy().to_string(),
         relative_path,
+        reused,
     })
 }
 
-fn load_history_index() -> Result<HistoryIndex, AppError> {
-    let index_path = history_index_path()?;
-    if !index_path.exists() {
-        return Ok(HistoryIndex::default());
-    }
-
-    let raw = read_utf8(&index_path)?;
-    serde_json::from_str::<HistoryIndex>(&raw)
-        .map_err(|error| AppError::new(AppErrorCode::Io, error.to_string()))
+fn image_error(error: image::ImageError) -> AppError {
+    AppError::new(AppErrorCode::Io, format!("Could not process image: {error}"))
 }
 
-fn save_history_index(index: &HistoryIndex) -> Result<(), AppError> {
-    let serialized = serde_json::to_string_pretty(index)
-        .map_err(|error| AppError::new(AppErrorCode::Io, error.to_string()))?;
-    let index_path = history_index_path()?;
-    atomic_write(&index_path, &serialized)
+fn flatten_on_white(image: &DynamicImage) -> RgbImage {
+    let rgba = image.to_rgba8();
+    RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let [red, green, blue, alpha] = rgba.get_pixel(x, y).0;
+        let blend = |channel: u8| ((u16::from(channel) * u16::from(alpha) + 255 * (255 - u16::from(alpha))) / 255) as u8;
+        Rgb([blend(red), blend(green), blend(blue)])
+    })
 }
 
-fn snapshot_dir_for_document(path: &str) -> Result<PathBuf, AppError> {
-    let key = format!("{:x}", hash_u64(path));
-    Ok(history_dir()?.join(key))
+fn encode_image(image: &DynamicImage, extension: &str, quality: u8) -> Result<Vec<u8>, AppError> {
+    let mut output = Vec::new();
+    match extension {
+        "jpg" => flatten_on_white(image)
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut output, quality.clamp(1, 100)))
+            .map_err(image_error)?,
+        "webp" => image
+            .to_rgba8()
+            .write_with_encoder(WebPEncoder::new_lossless(&mut output))
+            .map_err(image_error)?,
+        _ => image
+            .write_with_encoder(PngEncoder::new_with_quality(
+                &mut output,
+                CompressionType::Best,
+                PngFilterType::Adaptive,
+            ))
+            .map_err(image_error)?,
+    }
+    Ok(output)
 }
 
-fn split_link_and_anchor(link: &str) -> (String, Option<String>) {
-    if let Some(anchor) = link.strip_prefix('#') {
-        return (String::new(), Some(anchor.to_string()));
+fn strip_jpeg_metadata(bytes: &[u8]) -> Option<Vec<u8>> {
+    if !bytes.starts_with(&[0xFF, 0xD8]) {
+        return None;
     }
-
-    if let Some(index) = link.find('#') {
-        (
-            link[..index].to_string(),
-            Some(link[index + 1..].to_string()),
-        )
-    } else {
-        (link.to_string(), None)
+    let mut output = vec![0xFF, 0xD8];
+    let mut position = 2;
+    while position < bytes.len() {
+        if bytes[position] != 0xFF {
+            return None;
+        }
+        let marker = *bytes.get(position + 1)?;
+        if marker == 0xFF {
+            position += 1;
+            continue;
+        }
+        if marker == 0xDA {
+            output.extend_from_slice(&bytes[position..]);
+            return Some(output);
+        }
+        if matches!(marker, 0x01 | 0xD0..=0xD9) {
+            output.extend_from_slice(&bytes[position..position + 2]);
+            position += 2;
+            continue;
+        }
+        let length = u16::from_be_bytes([*bytes.get(position + 2)?, *bytes.get(position + 3)?]) as usize;
+        if length < 2 {
+            return None;
+        }
+        let end = position + 2 + length;
+        let segment = bytes.get(position..end)?;
+        let payload = segment.get(4..)?;
+        let keep = match marker {
+            0xE2 => payload.starts_with(b"ICC_PROFILE\0"),
+            0xEE => payload.starts_with(b"Adobe"),
+            0xE1..=0xEF | 0xFE => false,
+            _ => true,
+        };
+        if keep {
+            output.extend_from_slice(segment);
+        }
+        position = end;
     }
+    None
 }
 
-fn slugify_heading(input: &str) -> String {
-    let mut slug = String::new();
-    let mut last_dash = false;
-
-    for ch in input.chars() {
-        if ch.is_ascii_alphanumeric() {
-            slug.push(ch.to_ascii_lowercase());
-            last_dash = false;
-        } else if (ch.is_whitespace() || ch == '-' || ch == '_') && !last_dash && !slug.is_empty() {
-            slug.push('-');
-            last_dash = true;
+fn strip_png_metadata(bytes: &[u8]) -> Option<Vec<u8>> {
+    if !bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return None;
+    }
+    let mut output = bytes[..8].to_vec();
+    let mut position = 8;
+    while position < bytes.len() {
+        let length = u32::from_be_bytes(bytes.get(position..position + 4)?.try_into().ok()?) as usize;
+        let end = position.checked_add(12 + length)?;
+        let chunk = bytes.get(position..end)?;
+        if !matches!(&chunk[4..8], b"eXIf" | b"tEXt" | b"zTXt" | b"iTXt" | b"tIME") {
+            output.extend_from_slice(chunk);
         }
+        position = end;
     }
+    Some(output)
+}
 
-    while slug.ends_with('-') {
-        slug.pop();
+fn strip_webp_metadata(bytes: &[u8]) -> Option<Vec<u8>> {
+    if bytes.len() < 12 || !bytes.starts_with(b"RIFF") || &bytes[8..12] != b"WEBP" {
+        return None;
     }
-
-    slug
+    let mut output = bytes[..12].to_vec();
+    let mut position = 12;
+    while position < bytes.len() {
+        let length = u32::from_le_bytes(bytes.get(position + 4..position + 8)?.try_into().ok()?) as usize;
+        let end = position.checked_add(8 + length + length % 2)?.min(bytes.len());
+        let chunk = bytes.get(position..end)?;
+        match &chunk[..4] {
+            b"EXIF" | b"XMP " => {}
+            b"VP8X" if chunk.len() > 8 => {
+                let mut chunk = chunk.to_vec();
+                chunk[8] &= !0x0C;
+                output.extend_from_slice(&chunk);
+            }
+            _ => output.extend_from_slice(chunk),
+        }
+        position = end;
+    }
+    let riff_size = (output.len() - 8) as u32;
+    output[4..8].copy_from_slice(&riff_size.to_le_bytes());
+    Some(output)
 }
 
-fn heading_slugs(markdown: &str) -> HashSet<String> {
-    let mut slugs = HashSet::new();
+// Removes EXIF, XMP, IPTC and text metadata without touching the encoded pixels.
+fn strip_image_metadata(bytes: &[u8], extension: &str) -> Option<Vec<u8>> {
+    match extension {
+        "jpg" | "jpeg" => strip_jpeg_metadata(bytes),
+        "png" => strip_png_metadata(bytes),
+        "webp" => strip_webp_metadata(bytes),
+        "bmp" => Some(bytes.to_vec()),
+        _ => None,
+    }
+}
 
-    for line in markdown.lines() {
-        let trimmed = line.trim_start();
-        let hashes = trimmed.chars().take_while(|ch| *ch == '#').count();
-        if hashes == 0 || hashes > 6 {
-            continue;
-        }
+fn process_image_bytes(
+    bytes: &[u8],
+    extension: &str,
+    settings: &ImageProcessingSettings,
+) -> Result<(Vec<u8>, String), AppError> {
+    if !settings.enabled || matches!(extension, "svg" | "gif") {
+        return Ok((bytes.to_vec(), extension.to_string()));
+    }
 
-        let content = trimmed[hashes..].trim();
-        if content.is_empty() {
-            continue;
-        }
+    let decoder = || -> Result<_, AppError> {
+        ImageReader::new(Cursor::new(bytes))
+            .with_guessed_format()
+            .map_err(|error| map_io_error(&error))?
+            .into_decoder()
+            .map_err(image_error)
+    };
+    let mut probe = decoder()?;
+    let (width, height) = probe.dimensions();
+    let orientation = probe.orientation().unwrap_or(image::metadata::Orientation::NoTransforms);
+
+    let max_dimension = settings
+        .max_dimension
+        .filter(|max| *max > 0 && (width > *max || height > *max));
+    let target = match settings.format.as_str() {
+        "jpeg" | "jpg" => "jpg",
+        "webp" => "webp",
+        "png" => "png",
+        _ if matches!(extension, "jpg" | "jpeg") => "jpg",
+        _ if extension == "webp" => "webp",
+        _ => "png",
+    };
+    let converted = target != extension && !(target == "jpg" && extension == "jpeg");
+    let reorient = settings.strip_metadata && orientation != image::metadata::Orientation::NoTransforms;
 
-        let slug = slugify_heading(content);
-        if !slug.is_empty() {
-            slugs.insert(slug);
+    if max_dimension.is_none() && !converted && !reorient {
+        let original = if settings.strip_metadata {
+            strip_image_metadata(bytes, extension)
+        } else {
+            Some(bytes.to_vec())
+        };
+        if let Some(original) = original {
+            return Ok((original, extension.to_string()));
         }
+        probe = decoder()?;
     }
 
-    slugs
+    let mut image = DynamicImage::from_decoder(probe).map_err(image_error)?;
+    image.apply_orientation(orientation);
+    if let Some(max) = max_dimension {
+        image = image.resize(max, max, FilterType::Lanczos3);
+    }
+    Ok((encode_image(&image, target, settings.quality)?, target.to_string()))
 }
 
-fn normalize_link_target(raw: &str) -> String {
-    let mut value = raw.trim().to_string();
-    if value.starts_with('<') && value.ends_with('>') && value.len() > 2 {
-        value = value[1..value.len() - 1].to_string();
+fn sniff_image_format(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Some("png");
+    }
+    if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        return Some("jpg");
+    }
+    if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        return Some("gif");
+    }
+    if bytes.len() >= 12 && bytes.starts_with(b"RIFF") && &bytes[8..12] == b"WEBP" {
+        return Some("webp");
+    }
+    if bytes.starts_with(b"BM") && bytes.len() >= 26 {
+        return Some("bmp");
     }
 
-    if let Some(index) = value.find(char::is_whitespace) {
-        value = value[..index].to_string();
+    let head = String::from_utf8_lossy(&bytes[..bytes.len().min(4096)]).to_ascii_lowercase();
+    let head = head.trim_start_matches('\u{feff}').trim_start();
+    if (head.starts_with("<?xml") || head.starts_with("<svg") || head.starts_with("<!--") || head.starts_with("<!doctype"))
+        && head.contains("<svg")
+    {
+        return Some("svg");
     }
+    None
+}
 
-    value
-        .trim_matches('"')
-        .trim_matches('\'')
-        .trim()
-        .to_string()
+const SVG_ALLOWED_ELEMENTS: &[&str] = &[
+    "svg", "g", "defs", "title", "desc", "symbol", "use", "path", "rect", "circle", "ellipse", "line",
+    "polyline", "polygon", "text", "tspan", "textPath", "image", "linearGradient", "radialGradient", "stop",
+    "pattern", "clipPath", "mask", "marker", "style", "filter", "feBlend", "feColorMatrix", "feComposite",
+    "feDropShadow", "feFlood", "feGaussianBlur", "feMerge", "feMergeNode", "feMorphology", "feOffset",
+];
+
+const SVG_ALLOWED_ATTRIBUTES: &[&str] = &[
+    "id", "class", "style", "x", "y", "x1", "y1", "x2", "y2", "cx", "cy", "r", "rx", "ry", "fx", "fy", "fr",
+    "width", "height", "d", "points", "transform", "viewBox", "preserveAspectRatio", "version", "fill",
+    "fill-opacity", "fill-rule", "stroke", "stroke-width", "stroke-linecap", "stroke-linejoin",
+    "stroke-miterlimit", "stroke-dasharray", "stroke-dashoffset", "stroke-opacity", "opacity", "offset",
+    "stop-color", "stop-opacity", "gradientUnits", "gradientTransform", "spreadMethod", "patternUnits",
+    "patternContentUnits", "patternTransform", "clipPathUnits", "clip-path", "clip-rule", "mask", "maskUnits",
+    "maskContentUnits", "marker-start", "marker-mid", "marker-end", "markerWidth", "markerHeight",
+    "markerUnits", "refX", "refY", "orient", "font-family", "font-size", "font-weight", "font-style",
+    "text-anchor", "dominant-baseline", "letter-spacing", "word-spacing", "dx", "dy", "rotate", "textLength",
+    "lengthAdjust", "startOffset", "visibility", "display", "color", "overflow", "vector-effect",
+    "shape-rendering", "paint-order", "filter", "filterUnits", "primitiveUnits", "in", "in2", "result",
+    "stdDeviation", "mode", "operator", "k1", "k2", "k3", "k4", "type", "values", "radius", "flood-color",
+    "flood-opacity", "lighting-color", "href", "xlink:href", "xml:space",
+];
+
+const SVG_NAMESPACE: &str = "http://www.w3.org/2000/svg";
+const XLINK_NAMESPACE: &str = "http://www.w3.org/1999/xlink";
+
+fn is_safe_svg_reference(value: &str) -> bool {
+    let value = value.trim().to_ascii_lowercase();
+    value.starts_with('#')
+        || ["data:image/png", "data:image/jpeg", "data:image/gif", "data:image/webp"]
+            .iter()
+            .any(|prefix| value.starts_with(prefix))
 }
 
-fn extract_markdown_links(markdown: &str) -> Vec<(u32, String)> {
-    let markdown_link_regex = Regex::new(r#"!?\[[^\]]*\]\(([^\)]+)\)"#).expect("valid regex");
-    let auto_link_regex = Regex::new(r#"<(https?://[^>\s]+)>"#).expect("valid regex");
+fn sanitize_svg_css(css: &str) -> String {
+    let mut sanitized = css.to_string();
+    if let Ok(import) = Regex::new(r"(?i)@import[^;]*;?") {
+        sanitized = import.replace_all(&sanitized, "").to_string();
+    }
+    if let Ok(url) = Regex::new(r#"(?i)url\(\s*['"]?([^)'"]*)['"]?\s*\)"#) {
+        sanitized = url
+            .replace_all(&sanitized, |captures: &regex::Captures| {
+                if is_safe_svg_reference(&captures[1]) {
+                    captures[0].to_string()
+                } else {
+                    "none".to_string()
+                }
+            })
+            .to_string();
+    }
+    sanitized
+}
 
-    let mut links = Vec::new();
+fn sanitize_svg_element(element: &BytesStart) -> Result<Option<BytesStart<'static>>, AppError> {
+    let name = String::from_utf8_lossy(element.name().as_ref()).to_string();
+    if !SVG_ALLOWED_ELEMENTS.contains(&name.as_str()) {
+        return Ok(None);
+    }
 
-    for (index, line) in markdown.lines().enumerate() {
-        let line_number = (index + 1) as u32;
+    let mut sanitized = BytesStart::new(name);
+    for attribute in element.attributes() {
+        let attribute = attribute.map_err(|_| svg_error())?;
+        let key = String::from_utf8_lossy(attribute.key.as_ref()).to_string();
+        let value = attribute.unescape_value().map_err(|_| svg_error())?;
+        let keep = match key.as_str() {
+            "xmlns" => value == SVG_NAMESPACE,
+            "xmlns:xlink" => value == XLINK_NAMESPACE,
+            "href" | "xlink:href" => is_safe_svg_reference(&value),
+            _ => SVG_ALLOWED_ATTRIBUTES.contains(&key.as_str()),
+        };
+        if !keep {
+            continue;
+        }
+        if key == "style" {
+            sanitized.push_attribute((key.as_str(), sanitize_svg_css(&value).as_str()));
+        } else {
+            sanitized.push_attribute((key.as_str(), value.as_ref()));
+        }
+    }
+    Ok(Some(sanitized))
+}
 
-        for captures in markdown_link_regex.captures_iter(line) {
-            if let Some(target) = captures.get(1) {
-                let value = normalize_link_target(target.as_str());
-                if !value.is_empty() {
-                    links.push((line_number, value));
+fn svg_error() -> AppError {
+    AppError::new(AppErrorCode::InvalidEncoding, "SVG data is not well-formed")
+}
+
+fn sanitize_svg(svg: &str) -> Result<String, AppError> {
+    let mut reader = XmlReader::from_str(svg);
+    let mut writer = XmlWriter::new(Vec::new());
+    let mut open: Vec<String> = Vec::new();
+    let mut skip_depth = 0usize;
+    let mut saw_root = false;
+    let mut text = String::new();
+    let write = |writer: &mut XmlWriter<Vec<u8>>, event: XmlEvent| writer.write_event(event).map_err(|_| svg_error());
+
+    loop {
+        let event = reader.read_event().map_err(|_| svg_error())?;
+        let collecting = skip_depth == 0 && !open.is_empty();
+        match &event {
+            XmlEvent::Text(content) => {
+                if collecting {
+                    text.push_str(&content.decode().map_err(|_| svg_error())?);
                 }
+                continue;
+            }
+            XmlEvent::CData(content) => {
+                if collecting {
+                    text.push_str(&content.decode().map_err(|_| svg_error())?);
+                }
+                continue;
             }
+            XmlEvent::GeneralRef(reference) => {
+                let resolved = match reference.resolve_char_ref().map_err(|_| svg_error())? {
+                    Some(character) => character.to_string(),
+                    None => {
+                        let name = reference.decode().map_err(|_| svg_error())?;
+                        quick_xml::escape::resolve_predefined_entity(&name)
+                            .ok_or_else(svg_error)?
+                            .to_string()
+                    }
+                };
+                if collecting {
+                    text.push_str(&resolved);
+                }
+                continue;
+            }
+            _ => {}
         }
 
-        for captures in auto_link_regex.captures_iter(line) {
-            if let Some(target) = captures.get(1) {
-                links.push((line_number, target.as_str().to_string()));
+        if !text.is_empty() {
+            let content = std::mem::take(&mut text);
+            let content = if open.last().is_some_and(|name| name == "style") {
+                sanitize_svg_css(&content)
+            } else {
+                content
+            };
+            write(&mut writer, XmlEvent::Text(BytesText::new(&content)))?;
+        }
+
+        match event {
+            XmlEvent::Start(element) => {
+                if skip_depth > 0 {
+                    skip_depth += 1;
+                    continue;
+                }
+                if open.is_empty() && (saw_root || element.name().as_ref() != b"svg") {
+                    return Err(svg_error());
+                }
+                saw_root = true;
+                match sanitize_svg_element(&element)? {
+                    Some(sanitized) => {
+                        open.push(String::from_utf8_lossy(sanitized.name().as_ref()).to_string());
+                        write(&mut writer, XmlEvent::Start(sanitized))?;
+                    }
+                    None => skip_depth = 1,
+                }
+            }
+            XmlEvent::Empty(element) => {
+                if skip_depth > 0 {
+                    continue;
+                }
+                if open.is_empty() && (saw_root || element.name().as_ref() != b"svg") {
+                    return Err(svg_error());
+                }
+                saw_root = true;
+                if let Some(sanitized) = sanitize_svg_element(&element)? {
+                    write(&mut writer, XmlEvent::Empty(sanitized))?;
+                }
+            }
+            XmlEvent::End(_) => {
+                if skip_depth > 0 {
+                    skip_depth -= 1;
+                    continue;
+                }
+                let name = open.pop().ok_or_else(svg_error)?;
+                write(&mut writer, XmlEvent::End(BytesEnd::new(name)))?;
             }
+            XmlEvent::Decl(declaration) if !saw_root => write(&mut writer, XmlEvent::Decl(declaration))?,
+            XmlEvent::Eof => break,
+            _ => {}
         }
     }
 
-    links
+    if !saw_root || !open.is_empty() || skip_depth > 0 {
+        return Err(AppError::new(AppErrorCode::InvalidEncoding, "SVG data is incomplete"));
+    }
+    String::from_utf8(writer.into_inner()).map_err(|_| svg_error())
 }
 
-fn parse_external_host_port(url: &str) -> Option<(String, u16)> {
-    let (scheme, rest) = url.split_once("://")?;
-    let authority = rest
-        .split('/')
-        .next()
-        .unwrap_or(rest)
-        .split('?')
-        .next()
-        .unwrap_or(rest)
-        .split('#')
-        .next()
-        .unwrap_or(rest);
+fn validate_image_bytes(bytes: &[u8]) -> Result<(Vec<u8>, &'static str), AppError> {
+    let extension = sniff_image_format(bytes).ok_or_else(|| {
+        AppError::new(
+            AppErrorCode::InvalidEncoding,
+            "Data is not a supported image (PNG, JPEG, GIF, WebP, BMP or SVG)",
+        )
+    })?;
 
-    let authority = authority.rsplit('@').next().unwrap_or(authority);
+    if extension == "svg" {
+        let svg = std::str::from_utf8(bytes)
+            .map_err(|_| AppError::new(AppErrorCode::InvalidEncoding, "SVG must be UTF-8"))?;
+        return Ok((sanitize_svg(svg)?.into_bytes(), extension));
+    }
 
-    let default_port = if scheme.eq_ignore_ascii_case("https") {
-        443
+    let format = image::ImageFormat::from_extension(extension)
+        .ok_or_else(|| AppError::new(AppErrorCode::InvalidEncoding, "Unsupported image format"))?;
+    image::load_from_memory_with_format(bytes, format).map_err(|error| {
+        AppError::new(
+            AppErrorCode::InvalidEncoding,
+            format!("Image data is truncated or corrupt: {error}"),
+        )
+    })?;
+    Ok((bytes.to_vec(), extension))
+}
+
+fn document_workspace_settings(document_path: &Path) -> Result<(Option<PathBuf>, WorkspaceSettings), AppError> {
+    let store = load_settings_store(&settings_store_path()?)?;
+    Ok(match workspace_for_document(&store, document_path) {
+        Some((root, settings)) => (Some(root), settings),
+        None => (None, WorkspaceSettings::default()),
+    })
+}
+
+fn store_image_asset(document_path: &Path, preferred_name: &str, bytes: &[u8]) -> Result<SavedImageAsset, AppError> {
+    let (workspace_root, settings) = document_workspace_settings(document_path)?;
+    let (bytes, extension) = validate_image_bytes(bytes)?;
+    let (bytes, extension) = process_image_bytes(&bytes, extension, &settings.images)?;
+    save_asset_bytes(
+        document_path,
+        preferred_name,
+        &extension,
+        &bytes,
+        &settings.assets,
+        workspace_root.as_deref(),
+    )
+}
+
+fn escape_link_text(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('[', "\\[").replace(']', "\\]")
+}
+
+fn save_attachment_bytes(
+    document_path: &Path,
+    file_name: &str,
+    bytes: &[u8],
+    settings: &WorkspaceSettings,
+    workspace_root: Option<&Path>,
+) -> Result<SavedAttachment, AppError> {
+    let source = Path::new(file_name);
+    let extension = ext_from_path(source)
+        .ok_or_else(|| AppError::new(AppErrorCode::PermissionDenied, "Attachment has no file extension"))?;
+    let is_image = is_image_file(source);
+    let allowed = is_image
+        || settings
+            .attachments
+            .allowed_extensions
+            .iter()
+            .any(|allowed| allowed.trim_start_matches('.').eq_ignore_ascii_case(&extension));
+    if !allowed {
+        return Err(AppError::new(
+            AppErrorCode::PermissionDenied,
+            format!(".{extension} files are not allowed as attachments"),
+        ));
+    }
+    if bytes.len() as u64 > settings.attachments.max_size_bytes {
+        return Err(AppError::new(
+            AppErrorCode::PermissionDenied,
+            format!(
+                "Attachment is larger than the {:.1} MB limit",
+                settings.attachments.max_size_bytes as f64 / (1024.0 * 1024.0)
+            ),
+        ));
+    }
+
+    let stem = source
+        .file_stem()
+        .and_then(|value| value.to_str())
+        .unwrap_or("attachment");
+    let (bytes, extension) = if is_image {
+        let (bytes, extension) = validate_image_bytes(bytes)?;
+        process_image_bytes(&bytes, extension, &settings.images)?
     } else {
-        80
+        (bytes.to_vec(), extension)
     };
+    let saved = save_asset_bytes(document_path, stem, &extension, &bytes, &settings.assets, workspace_root)?;
 
-    if authority.starts_with('[') {
-        let end = authority.find(']')?;
-        let host = authority[1..end].to_string();
-        let port = authority[end + 1..]
-            .strip_prefix(':')
-            .and_then(|value| value.parse::<u16>().ok())
-            .unwrap_or(default_port);
-        return Some((host, port));
-    }
+    let label = source.file_name().and_then(|value| value.to_str()).unwrap_or(stem);
+    let link = encode_link_path(&saved.relative_path);
+    let markdown = if is_image {
+        format!("![{}]({})", escape_link_text(stem), link)
+    } else {
+        format!("[{}]({})", escape_link_text(label), link)
+    };
 
-    if let Some((host, port)) = authority.rsplit_once(':') {
-        if let Ok(parsed_port) = port.parse::<u16>() {
-            return Some((host.to_string(), parsed_port));
-        }
-    }
+    Ok(SavedAttachment {
+        path: saved.path,
+        relative_path: saved.relative_path,
+        reused: saved.reused,
+        size_bytes: bytes.len() as u64,
+        markdown,
+    })
+}
 
-    Some((authority.to_string(), default_port))
+const LARGE_IMAGE_BYTES: u64 = 2 * 1024 * 1024;
+const DEFAULT_THUMBNAIL_SIZE: u32 = 160;
+const MAX_THUMBNAIL_SIZE: u32 = 1024;
+
+fn thumbnail_cache_path(path: &Path, size: u32, cache_dir: &Path) -> Result<PathBuf, AppError> {
+    let metadata = fs::metadata(path).map_err(|error| map_io_error(&error))?;
+    let key = format!(
+        "{}:{}:{}:{}",
+        path.to_string_lossy(),
+        metadata.len(),
+        modified_ms(path)?,
+        size
+    );
+    Ok(cache_dir.join(format!("{}.png", &content_digest(key.as_bytes())[..32])))
 }
 
-fn external_url_reachable(url: &str) -> bool {
-    let (host, port) = match parse_external_host_port(url) {
-        Some(parsed) => parsed,
-        None => return false,
+fn image_thumbnail(path: &Path, size: u32, cache_dir: Option<&Path>) -> Result<Vec<u8>, AppError> {
+    let cache_path = match cache_dir {
+        Some(cache_dir) => Some(thumbnail_cache_path(path, size, cache_dir)?),
+        None => None,
     };
+    if let Some(cached) = cache_path.as_ref().and_then(|cache_path| fs::read(cache_path).ok()) {
+        return Ok(cached);
+    }
+
+    let image = ImageReader::open(path)
+        .map_err(|error| map_io_error(&error))?
+        .with_guessed_format()
+        .map_err(|error| map_io_error(&error))?
+        .decode()
+        .map_err(image_error)?;
+    let thumbnail = encode_image(&image.thumbnail(size, size), "png", 100)?;
+    if let Some(cache_path) = &cache_path {
+        atomic_write_bytes(cache_path, &thumbnail)?;
+    }
+    Ok(thumbnail)
+}
 
-    let addrs = match (host.as_str(), port).to_socket_addrs() {
-        Ok(iter) => iter.collect::<Vec<_>>(),
-        Err(_) => return false,
+fn image_info(
+    line: u32,
+    link: &str,
+    path: &Path,
+    thumbnail_size: Option<u32>,
+    cache_dir: Option<&Path>,
+) -> ImageInfo {
+    let mut info = ImageInfo {
+        line,
+        link: link.to_string(),
+        path: path.to_string_lossy().to_string(),
+        exists: path.is_file(),
+        format: ext_from_path(path),
+        width: None,
+        height: None,
+        size_bytes: fs::metadata(path).map(|metadata| metadata.len()).unwrap_or(0),
+        thumbnail: None,
+        error: None,
     };
+    if !info.exists || info.format.as_deref() == Some("svg") {
+        return info;
+    }
 
-    for addr in addrs.into_iter().take(3) {
-        if TcpStream::connect_timeout(&addr, Duration::from_secs(2)).is_ok() {
-            return true;
+    let reader = match ImageReader::open(path).and_then(|reader| reader.with_guessed_format()) {
+        Ok(reader) => reader,
+        Err(error) => {
+            info.error = Some(error.to_string());
+            return info;
         }
+    };
+    if let Some(format) = reader.format() {
+        info.format = format.extensions_str().first().map(|value| value.to_string());
     }
 
-    false
+    let result = reader.into_dimensions().map_err(image_error).and_then(|(width, height)| {
+        info.width = Some(width);
+        info.height = Some(height);
+        match thumbnail_size {
+            Some(size) => image_thumbnail(path, size.clamp(16, MAX_THUMBNAIL_SIZE), cache_dir)
+                .map(|bytes| info.thumbnail = Some(format!("data:image/png;base64,{}", BASE64_STANDARD.encode(bytes)))),
+            None => Ok(()),
+        }
+    });
+    if let Err(error) = result {
+        info.error = Some(error.message);
+    }
+    info
 }
 
-fn is_external_link(link: &str) -> bool {
-    link.starts_with("http://") || link.starts_with("https://")
+fn is_wiki_embed(lines: &[&str], link: &MarkdownLink) -> bool {
+    link.kind == LinkKind::Wiki
+        && link.column > 0
+        && lines
+            .get(link.line as usize - 1)
+            .and_then(|line| line.as_bytes().get(link.column - 1))
+            == Some(&b'!')
 }
 
-fn is_ignored_link(link: &str) -> bool {
-    link.starts_with("mailto:") || link.starts_with("tel:") || link.starts_with("javascript:")
-}
+fn document_image_info(
+    document_path: &Path,
+    markdown: &str,
+    workspace_root: Option<&Path>,
+    thumbnail_size: Option<u32>,
+    cache_dir: Option<&Path>,
+) -> Result<Vec<ImageInfo>, AppError> {
+    let document_dir = document_path
+        .parent()
+        .ok_or_else(|| AppError::new(AppErrorCode::Io, "Document path has no parent"))?;
+    let workspace_root = workspace_root.unwrap_or(document_dir);
+    let lines: Vec<&str> = markdown.lines().collect();
 
-#[tauri::command]
+    Ok(resolve_document_links(document_path, workspace_root, markdown)
+        .into_iter()
+        .filter_map(|resolved| {
+            let target = resolved.target_path?;
+            let is_image = match resolved.link.kind {
+                LinkKind::Image | LinkKind::HtmlImage => true,
+                LinkKind::Wiki => is_wiki_embed(&lines, &resolved.link) && is_image_file(&target),
+                _ => false,
+            };
+            if !is_image {
+                return None;
+            }
+            Some(image_info(
+                resolved.link.line,
+                &resolved.link.target,
+                &target,
+                thumbnail_size,
+                cache_dir,
+            ))
+        })
+        .collect())
+}
+
+const HOUR_MS: u64 = 60 * 60 * 1000;
+const DAY_MS: u64 = 24 * HOUR_MS;
+const WEEK_MS: u64 = 7 * DAY_MS;
+
+const MAX_DELTA_CHAIN: u32 = 16;
+
+struct HistoryLock {
+    _file: File,
+}
+
+fn lock_history(history_root: &Path) -> Result<HistoryLock, AppError> {
+    fs::create_dir_all(history_root).map_err(|error| map_io_error(&error))?;
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(history_root.join(".lock"))
+        .map_err(|error| map_io_error(&error))?;
+    file.lock().map_err(|error| map_io_error(&error))?;
+    Ok(HistoryLock { _file: file })
+}
+
+fn document_index_path(history_root: &Path, path: &str) -> PathBuf {
+    let digest = content_digest(path.as_bytes());
+    history_root
+        .join("documents")
+        .join(format!("{}.json", &digest[..16]))
+}
+
+// Every snapshot also gets a small metadata file of its own, so a document index that is
+// lost or corrupt can be rebuilt instead of leaving its blobs behind as orphans.
+fn snapshot_metadata_dir(history_root: &Path, path: &str) -> PathBuf {
+    let digest = content_digest(path.as_bytes());
+    history_root.join("records").join(&digest[..16])
+}
+
+fn snapshot_metadata_path(history_root: &Path, path: &str, snapshot_id: &str) -> PathBuf {
+    let digest = content_digest(snapshot_id.as_bytes());
+    snapshot_metadata_dir(history_root, path).join(format!("{}.json", &digest[..16]))
+}
+
+fn serialize_snapshot_metadata(path: &str, record: &SnapshotRecord) -> Result<String, AppError> {
+    let metadata = SnapshotMetadata {
+        path: path.to_string(),
+        record: record.clone(),
+    };
+    serde_json::to_string(&metadata).map_err(|error| AppError::new(AppErrorCode::Io, error.to_string()))
+}
+
+fn read_snapshot_metadata(dir: &Path) -> Vec<(PathBuf, SnapshotMetadata, String)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.path();
+            let bytes = fs::read(&path).ok()?;
+            let metadata = serde_json::from_slice::<SnapshotMetadata>(&bytes).ok()?;
+            Some((path, metadata, content_digest(&bytes)))
+        })
+        .collect()
+}
+
+fn recover_document_history(history_root: &Path, stem: &str, index: &mut HistoryIndex) -> bool {
+    let mut recovered: Vec<(PathBuf, SnapshotMetadata, String)> =
+        read_snapshot_metadata(&history_root.join("records").join(stem));
+    if recovered.is_empty() {
+        return false;
+    }
+    recovered.sort_by_key(|(_, metadata, _)| metadata.record.created_at_ms);
+    for (metadata_path, metadata, digest) in recovered {
+        let records = index.files.entry(metadata.path).or_default();
+        if records.iter().all(|record| record.id != metadata.record.id) {
+            records.push(metadata.record);
+            index.recovered += 1;
+        }
+        index.persisted_records.insert(metadata_path, digest);
+    }
+    true
+}
+
+fn quarantine_index_file(path: &Path, index: &mut HistoryIndex) {
+    let mut corrupt_path = path.as_os_str().to_owned();
+    corrupt_path.push(".corrupt");
+    let _ = fs::rename(path, PathBuf::from(corrupt_path));
+    index.quarantined += 1;
+    append_log("quarantine_history_index", &path.to_string_lossy());
+}
+
+fn load_history_index(history_root: &Path) -> Result<HistoryIndex, AppError> {
+    let mut index = HistoryIndex::default();
+    let mut migrated = false;
+
+    let legacy_index_path = history_root.join("index.json");
+    if legacy_index_path.exists() {
+        match serde_json::from_str::<HistoryIndex>(&read_utf8(&legacy_index_path)?) {
+            Ok(legacy) => {
+                index.files = legacy.files;
+                index.blobs = legacy.blobs;
+            }
+            Err(_) => quarantine_index_file(&legacy_index_path, &mut index),
+        }
+        migrated = true;
+    }
+
+    if let Ok(entries) = fs::read_dir(history_root.join("documents")) {
+        for entry in entries.flatten() {
+            let document_path = entry.path();
+            if document_path.extension().and_then(|value| value.to_str()) != Some("json") {
+                continue;
+            }
+            let parsed = fs::read(&document_path).ok().and_then(|bytes| {
+                let document = serde_json::from_slice::<DocumentHistory>(&bytes).ok()?;
+                Some((document, content_digest(&bytes)))
+            });
+            match parsed {
+                Some((document, digest)) => {
+                    let on_disk: HashSet<PathBuf> = fs::read_dir(snapshot_metadata_dir(history_root, &document.path))
+                        .map(|entries| entries.flatten().map(|entry| entry.path()).collect())
+                        .unwrap_or_default();
+                    for record in &document.records {
+                        let metadata_path = snapshot_metadata_path(history_root, &document.path, &record.id);
+                        if on_disk.contains(&metadata_path) {
+                            let serialized = serialize_snapshot_metadata(&document.path, record)?;
+                            index
+                                .persisted_records
+                                .insert(metadata_path, content_digest(serialized.as_bytes()));
+                        }
+                    }
+                    index.persisted.insert(document.path.clone(), digest);
+                    index.files.insert(document.path, document.records);
+                }
+                None => quarantine_index_file(&document_path, &mut index),
+            }
+        }
+    }
+
+    if let Ok(entries) = fs::read_dir(history_root.join("documents")) {
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let Some(stem) = name.strip_suffix(".json.corrupt") else {
+                continue;
+            };
+            if history_root.join("documents").join(format!("{stem}.json")).exists() {
+                continue;
+            }
+            if recover_document_history(history_root, stem, &mut index) {
+                migrated = true;
+            } else {
+                index.unrecovered += 1;
+            }
+        }
+    }
+
+    let blobs_path = history_root.join("blobs.json");
+    let stored_blobs = fs::read(&blobs_path).ok().and_then(|bytes| {
+        let blobs = serde_json::from_slice::<HashMap<String, BlobInfo>>(&bytes).ok()?;
+        Some((blobs, content_digest(&bytes)))
+    });
+    if let Some((blobs, digest)) = stored_blobs {
+        index.blobs.extend(blobs);
+        index.persisted_blobs = Some(digest);
+    }
+    let missing_blob = index
+        .files
+        .values()
+        .flatten()
+        .filter_map(|record| record.blob.as_ref())
+        .any(|id| !index.blobs.contains_key(id));
+    if missing_blob {
+        index.blobs = scan_blob_store(history_root);
+    }
+
+    if migrate_legacy_snapshots(history_root, &mut index)? {
+        migrated = true;
+    }
+    if migrated {
+        save_history_index(history_root, &mut index)?;
+        let _ = fs::remove_file(&legacy_index_path);
+    }
+    Ok(index)
+}
+
+fn save_history_index(history_root: &Path, index: &mut HistoryIndex) -> Result<(), AppError> {
+    let mut persisted = HashMap::new();
+    let mut persisted_records = HashMap::new();
+    for (path, records) in &index.files {
+        for record in records {
+            let metadata_path = snapshot_metadata_path(history_root, path, &record.id);
+            let serialized = serialize_snapshot_metadata(path, record)?;
+            let digest = content_digest(serialized.as_bytes());
+            if index.persisted_records.get(&metadata_path) != Some(&digest) {
+                atomic_write(&metadata_path, &serialized)?;
+            }
+            persisted_records.insert(metadata_path, digest);
+        }
+
+        let document = DocumentHistory {
+            path: path.clone(),
+            records: records.clone(),
+        };
+        let serialized = serde_json::to_string_pretty(&document)
+            .map_err(|error| AppError::new(AppErrorCode::Io, error.to_string()))?;
+        let digest = content_digest(serialized.as_bytes());
+        if index.persisted.get(path) != Some(&digest) {
+            atomic_write(&document_index_path(history_root, path), &serialized)?;
+        }
+        persisted.insert(path.clone(), digest);
+    }
+    for metadata_path in index.persisted_records.keys() {
+        if !persisted_records.contains_key(metadata_path) {
+            let _ = fs::remove_file(metadata_path);
+            if let Some(parent) = metadata_path.parent() {
+                let _ = fs::remove_dir(parent);
+            }
+        }
+    }
+    index.persisted_records = persisted_records;
+    for path in index.persisted.keys() {
+        if !persisted.contains_key(path) {
+            let index_path = document_index_path(history_root, path);
+            let mut corrupt_path = index_path.as_os_str().to_owned();
+            corrupt_path.push(".corrupt");
+            let _ = fs::remove_file(PathBuf::from(corrupt_path));
+            let _ = fs::remove_file(index_path);
+        }
+    }
+    index.persisted = persisted;
+
+    let serialized = serde_json::to_string_pretty(&index.blobs)
+        .map_err(|error| AppError::new(AppErrorCode::Io, error.to_string()))?;
+    let digest = content_digest(serialized.as_bytes());
+    if index.persisted_blobs.as_ref() != Some(&digest) {
+        atomic_write(&history_root.join("blobs.json"), &serialized)?;
+        index.persisted_blobs = Some(digest);
+    }
+
+    for path in index.pending_removals.drain(..) {
+        let _ = fs::remove_file(&path);
+        if let Some(parent) = path.parent() {
+            let _ = fs::remove_dir(parent);
+        }
+    }
+    Ok(())
+}
+
+fn collect_files_recursive(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_files_recursive(&path, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
+
+fn scan_blob_store(history_root: &Path) -> HashMap<String, BlobInfo> {
+    let mut files = Vec::new();
+    collect_files_recursive(&history_root.join("blobs"), &mut files);
+
+    let mut blobs = HashMap::new();
+    for file in files {
+        if file.extension().and_then(|value| value.to_str()) != Some("blob") {
+            continue;
+        }
+        let Some(id) = file.file_stem().and_then(|value| value.to_str()) else {
+            continue;
+        };
+        let Some(payload) = fs::read(&file).ok().and_then(|bytes| decompress_bytes(&bytes).ok()) else {
+            continue;
+        };
+        let base = match payload.split_first() {
+            Some((b'F', _)) => None,
+            Some((b'D', rest)) if rest.len() >= 80 => Some(String::from_utf8_lossy(&rest[..64]).to_string()),
+            _ => continue,
+        };
+        let stored_bytes = fs::metadata(&file).map(|metadata| metadata.len()).unwrap_or(0);
+        blobs.insert(
+            id.to_string(),
+            BlobInfo {
+                base,
+                depth: 0,
+                stored_bytes,
+            },
+        );
+    }
+
+    update_blob_depths(&mut blobs);
+    blobs
+}
+
+fn update_blob_depths(blobs: &mut HashMap<String, BlobInfo>) {
+    let ids: Vec<String> = blobs.keys().cloned().collect();
+    for id in ids {
+        let mut depth = 0;
+        let mut current = blobs.get(&id).and_then(|info| info.base.clone());
+        while let Some(base) = current {
+            depth += 1;
+            if depth > MAX_DELTA_CHAIN {
+                break;
+            }
+            current = blobs.get(&base).and_then(|info| info.base.clone());
+        }
+        if let Some(info) = blobs.get_mut(&id) {
+            info.depth = depth;
+        }
+    }
+}
+
+fn clean_history_orphans(history_root: &Path, index: &mut HistoryIndex) -> HistoryRepairReport {
+    let mut report = HistoryRepairReport {
+        quarantined_indexes: index.quarantined,
+        recovered_records: index.recovered,
+        ..HistoryRepairReport::default()
+    };
+    index.blobs = scan_blob_store(history_root);
+
+    let readable: HashSet<String> = index
+        .blobs
+        .keys()
+        .filter(|id| read_blob(history_root, index, id).is_ok())
+        .cloned()
+        .collect();
+    for records in index.files.values_mut() {
+        let before = records.len();
+        records.retain(|record| match &record.blob {
+            Some(id) => readable.contains(id),
+            None => Path::new(&record.file_path).exists(),
+        });
+        report.dropped_records += before - records.len();
+    }
+    index.files.retain(|_, records| !records.is_empty());
+
+    // Blobs of a document index that could not be rebuilt may be the only copy of its
+    // history, so nothing is deleted from the blob store until that is resolved.
+    let keep_all_blobs = index.unrecovered > 0;
+    let referenced = referenced_blobs(index);
+    if !keep_all_blobs {
+        index.blobs.retain(|id, _| referenced.contains(id));
+    }
+    let legacy_files: HashSet<PathBuf> = index
+        .files
+        .values()
+        .flatten()
+        .filter(|record| !record.file_path.is_empty())
+        .map(|record| PathBuf::from(&record.file_path))
+        .collect();
+
+    let mut files = Vec::new();
+    collect_files_recursive(history_root, &mut files);
+    for file in files {
+        let name = file.file_name().and_then(|value| value.to_str()).unwrap_or("");
+        let extension = file.extension().and_then(|value| value.to_str()).unwrap_or("");
+        if extension == "blob" {
+            let id = file.file_stem().and_then(|value| value.to_str()).unwrap_or("");
+            if !keep_all_blobs && !index.blobs.contains_key(id) {
+                index.pending_removals.push(file);
+                report.removed_blobs += 1;
+            }
+        } else if (extension == "mdsnap" && !legacy_files.contains(&file))
+            || (extension == "json"
+                && file.starts_with(history_root.join("records"))
+                && !index.persisted_records.contains_key(&file))
+            || (name.starts_with('.') && name.ends_with(".tmp"))
+        {
+            index.pending_removals.push(file);
+            report.removed_files += 1;
+        }
+    }
+
+    report
+}
+
+fn content_digest(content: &[u8]) -> String {
+    Sha256::digest(content)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+fn blob_path(history_root: &Path, id: &str) -> PathBuf {
+    history_root
+        .join("blobs")
+        .join(id.get(..2).unwrap_or("00"))
+        .join(format!("{id}.blob"))
+}
+
+fn compress_bytes(bytes: &[u8]) -> Result<Vec<u8>, AppError> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(bytes).map_err(|error| map_io_error(&error))?;
+    encoder.finish().map_err(|error| map_io_error(&error))
+}
+
+fn decompress_bytes(bytes: &[u8]) -> Result<Vec<u8>, AppError> {
+    let mut decoded = Vec::new();
+    ZlibDecoder::new(bytes)
+        .read_to_end(&mut decoded)
+        .map_err(|error| AppError::new(AppErrorCode::Io, format!("Snapshot data is corrupt: {error}")))?;
+    Ok(decoded)
+}
+
+fn store_blob(
+    history_root: &Path,
+    index: &mut HistoryIndex,
+    content: &[u8],
+    base: Option<&str>,
+    allow_delta: bool,
+) -> Result<String, AppError> {
+    let id = content_digest(content);
+    let path = blob_path(history_root, &id);
+    if index.blobs.contains_key(&id) && path.exists() {
+        return Ok(id);
+    }
+
+    let mut payload = Vec::with_capacity(content.len() + 1);
+    let mut base_info = None;
+    if let (true, Some(base_id)) = (allow_delta, base.filter(|base_id| *base_id != id)) {
+        let base_depth = index.blobs.get(base_id).map(|info| info.depth);
+        if let (Some(depth), Ok(base_content)) = (base_depth, read_blob(history_root, index, base_id)) {
+            if depth < MAX_DELTA_CHAIN {
+                let prefix = base_content
+                    .iter()
+                    .zip(content.iter())
+                    .take_while(|(left, right)| left == right)
+                    .count();
+                let max_suffix = base_content.len().min(content.len()) - prefix;
+                let suffix = base_content
+                    .iter()
+                    .rev()
+                    .zip(content.iter().rev())
+                    .take(max_suffix)
+                    .take_while(|(left, right)| left == right)
+                    .count();
+                let inserted = &content[prefix..content.len() - suffix];
+                if inserted.len() * 2 < content.len() {
+                    payload.push(b'D');
+                    payload.extend_from_slice(base_id.as_bytes());
+                    payload.extend_from_slice(&(prefix as u64).to_le_bytes());
+                    payload.extend_from_slice(&(suffix as u64).to_le_bytes());
+                    payload.extend_from_slice(inserted);
+                    base_info = Some((base_id.to_string(), depth + 1));
+                }
+            }
+        }
+    }
+    if payload.is_empty() {
+        payload.push(b'F');
+        payload.extend_from_slice(content);
+    }
+
+    write_blob(history_root, index, &id, &payload, base_info)?;
+    Ok(id)
+}
+
+fn write_blob(
+    history_root: &Path,
+    index: &mut HistoryIndex,
+    id: &str,
+    payload: &[u8],
+    base_info: Option<(String, u32)>,
+) -> Result<(), AppError> {
+    let compressed = compress_bytes(payload)?;
+    atomic_write_bytes(&blob_path(history_root, id), &compressed)?;
+    let (base, depth) = match base_info {
+        Some((base, depth)) => (Some(base), depth),
+        None => (None, 0),
+    };
+    index.blobs.insert(
+        id.to_string(),
+        BlobInfo {
+            base,
+            depth,
+            stored_bytes: compressed.len() as u64,
+        },
+    );
+    Ok(())
+}
+
+fn read_blob(history_root: &Path, index: &HistoryIndex, id: &str) -> Result<Vec<u8>, AppError> {
+    let mut chain = vec![id.to_string()];
+    let mut deltas: Vec<(usize, usize, Vec<u8>)> = Vec::new();
+
+    let mut content = loop {
+        let current = chain.last().cloned().unwrap_or_default();
+        let compressed = fs::read(blob_path(history_root, &current)).map_err(|error| map_io_error(&error))?;
+        let payload = decompress_bytes(&compressed)?;
+        match payload.split_first() {
+            Some((b'F', rest)) => break rest.to_vec(),
+            Some((b'D', rest)) if rest.len() >= 80 && chain.len() <= MAX_DELTA_CHAIN as usize + 1 => {
+                let base_id = String::from_utf8_lossy(&rest[..64]).to_string();
+                let prefix = u64::from_le_bytes(rest[64..72].try_into().unwrap_or_default()) as usize;
+                let suffix = u64::from_le_bytes(rest[72..80].try_into().unwrap_or_default()) as usize;
+                deltas.push((prefix, suffix, rest[80..].to_vec()));
+                chain.push(base_id);
+            }
+            _ => {
+                return Err(AppError::new(AppErrorCode::Io, "Snapshot data is corrupt"));
+            }
+        }
+    };
+
+    while let Some((prefix, suffix, inserted)) = deltas.pop() {
+        if prefix + suffix > content.len() {
+            return Err(AppError::new(AppErrorCode::Io, "Snapshot data is corrupt"));
+        }
+        let mut rebuilt = Vec::with_capacity(prefix + inserted.len() + suffix);
+        rebuilt.extend_from_slice(&content[..prefix]);
+        rebuilt.extend_from_slice(&inserted);
+        rebuilt.extend_from_slice(&content[content.len() - suffix..]);
+        content = rebuilt;
+    }
+
+    if content_digest(&content) != id || !index.blobs.is_empty() && !index.blobs.contains_key(id) {
+        return Err(AppError::new(AppErrorCode::Io, "Snapshot data is corrupt"));
+    }
+    Ok(content)
+}
+
+fn load_record_content(history_root: &Path, index: &HistoryIndex, record: &SnapshotRecord) -> Result<String, AppError> {
+    let bytes = match &record.blob {
+        Some(blob) => read_blob(history_root, index, blob)?,
+        None => {
+            let snapshot_path = PathBuf::from(&record.file_path);
+            if !snapshot_path.exists() {
+                return Err(AppError::new(
+                    AppErrorCode::FileNotFound,
+                    "Snapshot file is missing on disk",
+                ));
+            }
+            fs::read(&snapshot_path).map_err(|error| map_io_error(&error))?
+        }
+    };
+
+    String::from_utf8(bytes)
+        .map_err(|_| AppError::new(AppErrorCode::InvalidEncoding, "Snapshot must be UTF-8"))
+}
+
+fn referenced_blobs(index: &HistoryIndex) -> HashSet<String> {
+    let mut referenced = HashSet::new();
+    for record in index.files.values().flatten() {
+        let mut current = record.blob.clone();
+        while let Some(id) = current {
+            if !referenced.insert(id.clone()) {
+                break;
+            }
+            current = index.blobs.get(&id).and_then(|info| info.base.clone());
+        }
+    }
+    referenced
+}
+
+fn history_stored_bytes(index: &HistoryIndex) -> u64 {
+    let blob_bytes: u64 = referenced_blobs(index)
+        .iter()
+        .filter_map(|id| index.blobs.get(id))
+        .map(|info| info.stored_bytes)
+        .sum();
+    let legacy_bytes: u64 = index
+        .files
+        .values()
+        .flatten()
+        .filter(|record| record.blob.is_none())
+        .map(|record| record.size_bytes)
+        .sum();
+    blob_bytes + legacy_bytes
+}
+
+// Rewrites deltas whose base no snapshot uses any more as full blobs, so deleting a
+// snapshot actually frees its data instead of keeping it alive as a delta base.
+fn rebase_orphaned_deltas(history_root: &Path, index: &mut HistoryIndex) -> Result<(), AppError> {
+    let direct: HashSet<String> = index
+        .files
+        .values()
+        .flatten()
+        .filter_map(|record| record.blob.clone())
+        .collect();
+    let dependents: Vec<String> = direct
+        .iter()
+        .filter(|id| {
+            index
+                .blobs
+                .get(*id)
+                .and_then(|info| info.base.as_ref())
+                .is_some_and(|base| !direct.contains(base))
+        })
+        .cloned()
+        .collect();
+    if dependents.is_empty() {
+        return Ok(());
+    }
+
+    let mut contents = Vec::with_capacity(dependents.len());
+    for id in dependents {
+        if let Ok(content) = read_blob(history_root, index, &id) {
+            contents.push((id, content));
+        }
+    }
+    for (id, content) in contents {
+        let mut payload = Vec::with_capacity(content.len() + 1);
+        payload.push(b'F');
+        payload.extend_from_slice(&content);
+        write_blob(history_root, index, &id, &payload, None)?;
+    }
+    update_blob_depths(&mut index.blobs);
+    Ok(())
+}
+
+fn remove_unreferenced_blobs(history_root: &Path, index: &mut HistoryIndex) -> Result<(), AppError> {
+    rebase_orphaned_deltas(history_root, index)?;
+    if index.unrecovered > 0 {
+        return Ok(());
+    }
+    let referenced = referenced_blobs(index);
+    let stale: Vec<String> = index
+        .blobs
+        .keys()
+        .filter(|id| !referenced.contains(*id))
+        .cloned()
+        .collect();
+    for id in stale {
+        index.blobs.remove(&id);
+        index.pending_removals.push(blob_path(history_root, &id));
+    }
+    Ok(())
+}
+
+fn migrate_legacy_snapshots(history_root: &Path, index: &mut HistoryIndex) -> Result<bool, AppError> {
+    let legacy_paths: Vec<String> = index
+        .files
+        .iter()
+        .filter(|(_, records)| records.iter().any(|record| record.blob.is_none()))
+        .map(|(path, _)| path.clone())
+        .collect();
+    if legacy_paths.is_empty() {
+        return Ok(false);
+    }
+
+    for path in legacy_paths {
+        let mut records = index.files.remove(&path).unwrap_or_default();
+        records.sort_by_key(|record| record.created_at_ms);
+        let mut previous_blob: Option<String> = None;
+        let mut migrated = Vec::with_capacity(records.len());
+
+        for mut record in records {
+            if record.blob.is_none() {
+                let legacy_path = PathBuf::from(&record.file_path);
+                let Ok(bytes) = fs::read(&legacy_path) else {
+                    continue;
+                };
+                let blob = store_blob(history_root, index, &bytes, previous_blob.as_deref(), true)?;
+                index.pending_removals.push(legacy_path);
+                record.blob = Some(blob);
+                record.file_path = String::new();
+            }
+            previous_blob = record.blob.clone();
+            migrated.push(record);
+        }
+
+        index.files.insert(path, migrated);
+    }
+
+    Ok(true)
+}
+
+fn snapshot_entry(record: &SnapshotRecord) -> SnapshotEntry {
+    SnapshotEntry {
+        id: record.id.clone(),
+        created_at_ms: record.created_at_ms,
+        reason: record.reason.clone(),
+        size_bytes: record.size_bytes,
+        pinned: record.pinned,
+        name: record.name.clone(),
+        notes: record.notes.clone(),
+    }
+}
+
+fn snapshot_record_mut<'a>(
+    index: &'a mut HistoryIndex,
+    path: &str,
+    snapshot_id: &str,
+) -> Result<&'a mut SnapshotRecord, AppError> {
+    index
+        .files
+        .get_mut(path)
+        .and_then(|records| records.iter_mut().find(|record| record.id == snapshot_id))
+        .ok_or_else(|| AppError::new(AppErrorCode::FileNotFound, "Snapshot not found"))
+}
+
+fn non_empty_label(value: Option<String>) -> Option<String> {
+    value
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+fn canonical_path(path: &Path) -> PathBuf {
+    if let Ok(canonical) = fs::canonicalize(path) {
+        return canonical;
+    }
+    let normalized = normalize_lexical_path(path);
+    if let (Some(parent), Some(name)) = (normalized.parent(), normalized.file_name()) {
+        if let Ok(parent) = fs::canonicalize(parent) {
+            return parent.join(name);
+        }
+    }
+    normalized
+}
+
+fn history_key(path: &str) -> String {
+    canonical_path(Path::new(path)).to_string_lossy().to_string()
+}
+
+fn merge_document_history(index: &mut HistoryIndex, from: &str, to: &str) -> bool {
+    if from == to {
+        return false;
+    }
+    let Some(moved) = index.files.remove(from) else {
+        return false;
+    };
+
+    let records = index.files.entry(to.to_string()).or_default();
+    for record in moved {
+        if !records.iter().any(|existing| existing.id == record.id) {
+            records.push(record);
+        }
+    }
+    records.sort_by_key(|record| record.created_at_ms);
+    true
+}
+
+fn resolve_history_key(index: &mut HistoryIndex, path: &str) -> String {
+    let key = history_key(path);
+    merge_document_history(index, path, &key);
+    key
+}
+
+fn load_document_history(history_root: &Path, path: &str) -> Result<(HistoryIndex, String), AppError> {
+    let mut index = load_history_index(history_root)?;
+    let key = history_key(path);
+    if merge_document_history(&mut index, path, &key) {
+        save_history_index(history_root, &mut index)?;
+    }
+    Ok((index, key))
+}
+
+fn move_document_history(history_root: &Path, old_path: &str, new_path: &str) -> Result<Vec<SnapshotEntry>, AppError> {
+    let _lock = lock_history(history_root)?;
+    let (mut index, old_key) = load_document_history(history_root, old_path)?;
+    let new_key = resolve_history_key(&mut index, new_path);
+    merge_document_history(&mut index, &old_key, &new_key);
+    save_history_index(history_root, &mut index)?;
+
+    let mut entries: Vec<SnapshotEntry> = index
+        .files
+        .get(&new_key)
+        .map(|records| records.iter().map(snapshot_entry).collect())
+        .unwrap_or_default();
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.created_at_ms));
+    Ok(entries)
+}
+
+fn history_candidates_in(history_root: &Path, path: &str) -> Result<Vec<HistoryCandidate>, AppError> {
+    let _lock = lock_history(history_root)?;
+    let (index, key) = load_document_history(history_root, path)?;
+    let content_hash = fs::read_to_string(path).ok().map(|content| hash_u64(&content));
+    let file_name = Path::new(&key).file_name().map(|name| name.to_os_string());
+
+    let mut candidates: Vec<HistoryCandidate> = index
+        .files
+        .iter()
+        .filter(|(candidate, _)| **candidate != key && !Path::new(candidate).exists())
+        .filter_map(|(candidate, records)| {
+            let last = records.iter().max_by_key(|record| record.created_at_ms)?;
+            let matches_content = content_hash.is_some_and(|hash| {
+                records.iter().any(|record| record.content_hash == hash)
+            });
+            let same_name = Path::new(candidate).file_name().map(|name| name.to_os_string()) == file_name;
+            (matches_content || same_name).then(|| HistoryCandidate {
+                path: candidate.clone(),
+                snapshot_count: records.len(),
+                last_snapshot_ms: last.created_at_ms,
+                matches_content,
+            })
+        })
+        .collect();
+
+    candidates.sort_by_key(|candidate| (!candidate.matches_content, std::cmp::Reverse(candidate.last_snapshot_ms)));
+    Ok(candidates)
+}
+
+fn word_changes(old: &str, new: &str) -> (u64, u64) {
+    let stats = diff_texts(old, new).stats;
+    (stats.words_added as u64, stats.words_removed as u64)
+}
+
+// Fills in word counts for snapshots recorded before they were tracked.
+fn backfill_word_counts(history_root: &Path, index: &mut HistoryIndex) -> bool {
+    let mut updates = Vec::new();
+    for (path, records) in &index.files {
+        if records
+            .iter()
+            .all(|record| record.word_count.is_some() && record.words_added.is_some())
+        {
+            continue;
+        }
+        let mut ordered: Vec<usize> = (0..records.len()).collect();
+        ordered.sort_by_key(|position| records[*position].created_at_ms);
+        let mut previous: Option<String> = Some(String::new());
+        for position in ordered {
+            let record = &records[position];
+            let content = load_record_content(history_root, index, record).ok();
+            if record.word_count.is_none() || record.words_added.is_none() {
+                let words = content
+                    .as_ref()
+                    .map(|content| content.split_whitespace().count() as u64)
+                    .or(record.word_count)
+                    .unwrap_or(0);
+                let changes = previous.as_deref().zip(content.as_deref()).map(|(old, new)| word_changes(old, new));
+                updates.push((path.clone(), position, words, changes));
+            }
+            previous = content;
+        }
+    }
+
+    let changed = !updates.is_empty();
+    for (path, position, words, changes) in updates {
+        if let Some(record) = index.files.get_mut(&path).and_then(|records| records.get_mut(position)) {
+            record.word_count = Some(words);
+            if let Some((added, removed)) = changes {
+                record.words_added = Some(added);
+                record.words_removed = Some(removed);
+            }
+        }
+    }
+    changed
+}
+
+fn activity_bucket_start(timestamp_ms: u64, bucket_ms: u64, offset_ms: i64) -> u64 {
+    let local = timestamp_ms as i64 + offset_ms;
+    (local.div_euclid(bucket_ms as i64) * bucket_ms as i64 - offset_ms).max(0) as u64
+}
+
+fn add_activity(files: &mut Vec<ActivityFileSummary>, path: &str, added: u64, removed: u64) {
+    match files.iter_mut().find(|file| file.path == path) {
+        Some(file) => {
+            file.edits += 1;
+            file.words_added += added;
+            file.words_removed += removed;
+        }
+        None => files.push(ActivityFileSummary {
+            path: path.to_string(),
+            edits: 1,
+            words_added: added,
+            words_removed: removed,
+        }),
+    }
+}
+
+fn activity_timeline(index: &HistoryIndex, query: &ActivityQuery) -> ActivityTimeline {
+    let bucket_ms = if query.granularity == "hour" { HOUR_MS } else { DAY_MS };
+    let offset_ms = query.utc_offset_minutes * 60 * 1000;
+    let workspace = query.workspace_folder.as_deref().map(history_key);
+
+    let mut buckets: Vec<ActivityBucket> = Vec::new();
+    let mut totals: Vec<ActivityFileSummary> = Vec::new();
+    for (path, records) in &index.files {
+        if workspace
+            .as_ref()
+            .is_some_and(|workspace| !Path::new(path).starts_with(workspace))
+        {
+            continue;
+        }
+
+        let mut ordered: Vec<&SnapshotRecord> = records.iter().collect();
+        ordered.sort_by_key(|record| record.created_at_ms);
+        let mut previous_words = 0;
+        for record in ordered {
+            let words = record.word_count.unwrap_or(previous_words);
+            let (added, removed) = match (record.words_added, record.words_removed) {
+                (Some(added), Some(removed)) => (added, removed),
+                _ => (words.saturating_sub(previous_words), previous_words.saturating_sub(words)),
+            };
+            previous_words = words;
+
+            let in_range = query.from_ms.is_none_or(|from| record.created_at_ms >= from)
+                && query.to_ms.is_none_or(|to| record.created_at_ms < to);
+            if !in_range {
+                continue;
+            }
+
+            let start_ms = activity_bucket_start(record.created_at_ms, bucket_ms, offset_ms);
+            let position = match buckets.iter().position(|bucket| bucket.start_ms == start_ms) {
+                Some(position) => position,
+                None => {
+                    buckets.push(ActivityBucket {
+                        start_ms,
+                        edits: 0,
+                        words_added: 0,
+                        words_removed: 0,
+                        files: Vec::new(),
+                    });
+                    buckets.len() - 1
+                }
+            };
+            let bucket = &mut buckets[position];
+            bucket.edits += 1;
+            bucket.words_added += added;
+            bucket.words_removed += removed;
+            add_activity(&mut bucket.files, path, added, removed);
+            add_activity(&mut totals, path, added, removed);
+        }
+    }
+
+    buckets.sort_by_key(|bucket| bucket.start_ms);
+    for bucket in &mut buckets {
+        bucket.files.sort_by(|left, right| right.edits.cmp(&left.edits).then_with(|| left.path.cmp(&right.path)));
+    }
+    totals.sort_by(|left, right| right.edits.cmp(&left.edits).then_with(|| left.path.cmp(&right.path)));
+
+    ActivityTimeline {
+        buckets,
+        files: totals,
+    }
+}
+
+fn search_snapshots_in(
+    history_root: &Path,
+    index: &HistoryIndex,
+    tokens: &[String],
+    document: Option<&str>,
+    max_results: usize,
+) -> Vec<SnapshotSearchHit> {
+    let mut snapshots: Vec<(&String, &SnapshotRecord)> = index
+        .files
+        .iter()
+        .filter(|(path, _)| document.is_none_or(|document| document == path.as_str()))
+        .flat_map(|(path, records)| records.iter().map(move |record| (path, record)))
+        .collect();
+    snapshots.sort_by_key(|(_, record)| std::cmp::Reverse(record.created_at_ms));
+
+    let mut seen = HashSet::new();
+    let mut hits = Vec::new();
+    for (path, record) in snapshots {
+        if hits.len() >= max_results {
+            break;
+        }
+        let content_key = record.blob.clone().unwrap_or_else(|| record.id.clone());
+        if !seen.insert((path.clone(), content_key)) {
+            continue;
+        }
+        let Ok(content) = load_record_content(history_root, index, record) else {
+            continue;
+        };
+
+        let lower_content = content.to_ascii_lowercase();
+        if !tokens.iter().all(|token| lower_content.contains(token)) {
+            continue;
+        }
+
+        let first_index = lower_content.find(&tokens[0]).unwrap_or(0);
+        let line = (lower_content[..first_index]
+            .bytes()
+            .filter(|byte| *byte == b'\n')
+            .count()
+            + 1) as u32;
+
+        hits.push(SnapshotSearchHit {
+            path: path.clone(),
+            snapshot_id: record.id.clone(),
+            created_at_ms: record.created_at_ms,
+            name: record.name.clone(),
+            line,
+            snippet: build_snippet(&content, first_index),
+        });
+    }
+    hits
+}
+
+fn export_history_archive(history_root: &Path, path: &str, archive_path: &Path) -> Result<usize, AppError> {
+    let _lock = lock_history(history_root)?;
+    let (index, key) = load_document_history(history_root, path)?;
+    let records = index.files.get(&key).cloned().unwrap_or_default();
+    if records.is_empty() {
+        return Err(AppError::new(
+            AppErrorCode::FileNotFound,
+            "No snapshots available for this document",
+        ));
+    }
+
+    let mut archive = HistoryArchive {
+        version: 1,
+        path: key,
+        records: Vec::with_capacity(records.len()),
+        contents: HashMap::new(),
+    };
+    for mut record in records {
+        let content = load_record_content(history_root, &index, &record)?;
+        let digest = content_digest(content.as_bytes());
+        record.blob = Some(digest.clone());
+        record.file_path = String::new();
+        archive.contents.entry(digest).or_insert(content);
+        archive.records.push(record);
+    }
+
+    let serialized = serde_json::to_vec(&archive)
+        .map_err(|error| AppError::new(AppErrorCode::Io, error.to_string()))?;
+    atomic_write_bytes(archive_path, &compress_bytes(&serialized)?)?;
+    Ok(archive.records.len())
+}
+
+fn import_history_archive(
+    history_root: &Path,
+    archive_path: &Path,
+    path: &str,
+    storage: &HistoryStorageSettings,
+) -> Result<Vec<SnapshotEntry>, AppError> {
+    let compressed = fs::read(archive_path).map_err(|error| map_io_error(&error))?;
+    let archive = serde_json::from_slice::<HistoryArchive>(&decompress_bytes(&compressed)?)
+        .map_err(|_| AppError::new(AppErrorCode::Io, "History archive is corrupt"))?;
+    for (digest, content) in &archive.contents {
+        if content_digest(content.as_bytes()) != *digest {
+            return Err(AppError::new(AppErrorCode::Io, "History archive is corrupt"));
+        }
+    }
+
+    let _lock = lock_history(history_root)?;
+    let (mut index, key) = load_document_history(history_root, path)?;
+    let mut records = index.files.remove(&key).unwrap_or_default();
+    let mut incoming = archive.records;
+    incoming.sort_by_key(|record| record.created_at_ms);
+
+    let mut previous_blob = records.last().and_then(|record| record.blob.clone());
+    for mut record in incoming {
+        if records.iter().any(|existing| existing.id == record.id) {
+            continue;
+        }
+        let content = record
+            .blob
+            .as_ref()
+            .and_then(|digest| archive.contents.get(digest))
+            .ok_or_else(|| AppError::new(AppErrorCode::Io, "History archive is corrupt"))?;
+        let blob = store_blob(
+            history_root,
+            &mut index,
+            content.as_bytes(),
+            previous_blob.as_deref(),
+            storage.delta_encoding,
+        )?;
+        previous_blob = Some(blob.clone());
+        record.blob = Some(blob);
+        record.file_path = String::new();
+        records.push(record);
+    }
+    records.sort_by_key(|record| record.created_at_ms);
+
+    let mut entries: Vec<SnapshotEntry> = records.iter().map(snapshot_entry).collect();
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.created_at_ms));
+    index.files.insert(key, records);
+    save_history_index(history_root, &mut index)?;
+    Ok(entries)
+}
+
+fn delete_snapshot_in(history_root: &Path, path: &str, snapshot_id: &str) -> Result<(), AppError> {
+    let _lock = lock_history(history_root)?;
+    let (mut index, path) = load_document_history(history_root, path)?;
+    let path = path.as_str();
+    let records = index
+        .files
+        .get_mut(path)
+        .ok_or_else(|| AppError::new(AppErrorCode::FileNotFound, "Snapshot not found"))?;
+    let position = records
+        .iter()
+        .position(|record| record.id == snapshot_id)
+        .ok_or_else(|| AppError::new(AppErrorCode::FileNotFound, "Snapshot not found"))?;
+
+    let removed = records.remove(position);
+    if records.is_empty() {
+        index.files.remove(path);
+    }
+    remove_snapshot_files(&mut index, &[removed]);
+    remove_unreferenced_blobs(history_root, &mut index)?;
+    save_history_index(history_root, &mut index)
+}
+
+fn load_settings_store(path: &Path) -> Result<SettingsStore, AppError> {
+    if !path.exists() {
+        return Ok(SettingsStore::default());
+    }
+
+    let raw = read_utf8(path)?;
+    serde_json::from_str::<SettingsStore>(&raw)
+        .map_err(|error| AppError::new(AppErrorCode::Io, error.to_string()))
+}
+
+fn save_settings_store(path: &Path, store: &SettingsStore) -> Result<(), AppError> {
+    let serialized = serde_json::to_string_pretty(store)
+        .map_err(|error| AppError::new(AppErrorCode::Io, error.to_string()))?;
+    atomic_write(path, &serialized)
+}
+
+fn workspace_for_document(store: &SettingsStore, document_path: &Path) -> Option<(PathBuf, WorkspaceSettings)> {
+    store
+        .workspaces
+        .iter()
+        .filter(|(folder, _)| document_path.starts_with(folder))
+        .max_by_key(|(folder, _)| Path::new(folder).components().count())
+        .map(|(folder, settings)| (PathBuf::from(folder), settings.clone()))
+}
+
+fn workspace_settings_for(store: &SettingsStore, document_path: &Path) -> WorkspaceSettings {
+    workspace_for_document(store, document_path)
+        .map(|(_, settings)| settings)
+        .unwrap_or_default()
+}
+
+fn retained_snapshot_ids(
+    records: &[SnapshotRecord],
+    retention: &HistoryRetentionSettings,
+    now: u64,
+) -> HashSet<String> {
+    let mut ordered: Vec<&SnapshotRecord> = records.iter().collect();
+    ordered.sort_by_key(|record| std::cmp::Reverse(record.created_at_ms));
+
+    let mut kept = HashSet::new();
+    let mut seen_buckets: HashSet<(u8, u64)> = HashSet::new();
+    for (position, record) in ordered.iter().enumerate() {
+        let age = now.saturating_sub(record.created_at_ms);
+        let keep = if record.pinned || position == 0 || age < retention.keep_all_for_ms {
+            true
+        } else {
+            let bucket = if age < retention.hourly_for_ms {
+                Some((1, record.created_at_ms / HOUR_MS))
+            } else if age < retention.daily_for_ms {
+                Some((2, record.created_at_ms / DAY_MS))
+            } else if retention.weekly_for_ms.is_none_or(|limit| age < limit) {
+                Some((3, record.created_at_ms / WEEK_MS))
+            } else {
+                None
+            };
+            bucket.is_some_and(|bucket| seen_buckets.insert(bucket))
+        };
+
+        if keep {
+            kept.insert(record.id.clone());
+        }
+    }
+
+    if let Some(limit) = retention.max_snapshots_per_file {
+        let overflow: Vec<String> = ordered
+            .iter()
+            .enumerate()
+            .filter(|(_, record)| kept.contains(&record.id) && !record.pinned)
+            .skip(limit as usize)
+            .filter(|(position, _)| *position != 0)
+            .map(|(_, record)| record.id.clone())
+            .collect();
+        for id in overflow {
+            kept.remove(&id);
+        }
+    }
+
+    kept
+}
+
+fn remove_snapshot_files(index: &mut HistoryIndex, records: &[SnapshotRecord]) {
+    for record in records {
+        if !record.file_path.is_empty() {
+            index.pending_removals.push(PathBuf::from(&record.file_path));
+        }
+    }
+}
+
+fn prune_document_history(
+    index: &mut HistoryIndex,
+    path: &str,
+    retention: &HistoryRetentionSettings,
+    now: u64,
+) {
+    let Some(records) = index.files.get_mut(path) else {
+        return;
+    };
+
+    let kept = retained_snapshot_ids(records, retention, now);
+    let (retained, pruned): (Vec<SnapshotRecord>, Vec<SnapshotRecord>) =
+        records.drain(..).partition(|record| kept.contains(&record.id));
+    *records = retained;
+    remove_snapshot_files(index, &pruned);
+}
+
+fn enforce_history_quota(history_root: &Path, index: &mut HistoryIndex, quota_bytes: u64) -> Result<(), AppError> {
+    remove_unreferenced_blobs(history_root, index)?;
+    if history_stored_bytes(index) <= quota_bytes {
+        return Ok(());
+    }
+
+    let mut candidates: Vec<(u64, String, String)> = Vec::new();
+    for (path, records) in &index.files {
+        let newest = records.iter().map(|record| record.created_at_ms).max();
+        for record in records {
+            if !record.pinned && Some(record.created_at_ms) != newest {
+                candidates.push((record.created_at_ms, path.clone(), record.id.clone()));
+            }
+        }
+    }
+    candidates.sort();
+
+    for (_, path, id) in candidates {
+        if history_stored_bytes(index) <= quota_bytes {
+            break;
+        }
+        let Some(records) = index.files.get_mut(&path) else {
+            continue;
+        };
+        if let Some(position) = records.iter().position(|record| record.id == id) {
+            let removed = records.remove(position);
+            remove_snapshot_files(index, &[removed]);
+            remove_unreferenced_blobs(history_root, index)?;
+        }
+    }
+    Ok(())
+}
+
+fn create_snapshot_in(
+    history_root: &Path,
+    path: &str,
+    content: &str,
+    reason: &str,
+    retention: &HistoryRetentionSettings,
+    storage: &HistoryStorageSettings,
+) -> Result<SnapshotEntry, AppError> {
+    let now = now_ms()?;
+    let _lock = lock_history(history_root)?;
+    let mut index = load_history_index(history_root)?;
+    let key = resolve_history_key(&mut index, path);
+    let path = key.as_str();
+
+    let content_hash = hash_u64(content);
+    let previous_blob = match index.files.get(path).and_then(|records| records.last()) {
+        Some(last) => {
+            let coalesce = reason == "autosave"
+                && last.reason == "autosave"
+                && now.saturating_sub(last.created_at_ms) < retention.autosave_coalesce_ms;
+            if last.content_hash == content_hash || coalesce {
+                return Ok(snapshot_entry(last));
+            }
+            last.blob.clone()
+        }
+        None => None,
+    };
+
+    let word_changes = match &previous_blob {
+        Some(previous) => read_blob(history_root, &index, previous)
+            .ok()
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+            .map(|previous| word_changes(&previous, content)),
+        None => Some(word_changes("", content)),
+    };
+    let blob = store_blob(
+        history_root,
+        &mut index,
+        content.as_bytes(),
+        previous_blob.as_deref(),
+        storage.delta_encoding,
+    )?;
+    let snapshot_id = format!(
+        "{}-{:x}",
+        now,
+        hash_u64(&format!("{}:{}:{}", path, now, content_hash))
+    );
+
+    let record = SnapshotRecord {
+        id: snapshot_id,
+        created_at_ms: now,
+        reason: reason.to_string(),
+        size_bytes: content.len() as u64,
+        file_path: String::new(),
+        content_hash,
+        pinned: false,
+        blob: Some(blob),
+        name: None,
+        notes: None,
+        word_count: Some(content.split_whitespace().count() as u64),
+        words_added: word_changes.map(|(added, _)| added),
+        words_removed: word_changes.map(|(_, removed)| removed),
+    };
+    let entry = snapshot_entry(&record);
+    index.files.entry(path.to_string()).or_default().push(record);
+
+    prune_document_history(&mut index, path, retention, now);
+    if let Some(quota_bytes) = storage.quota_bytes {
+        enforce_history_quota(history_root, &mut index, quota_bytes)?;
+    }
+    remove_unreferenced_blobs(history_root, &mut index)?;
+
+    save_history_index(history_root, &mut index)?;
+    Ok(entry)
+}
+
+fn git_error(error: git2::Error) -> AppError {
+    let code = match error.code() {
+        git2::ErrorCode::NotFound | git2::ErrorCode::UnbornBranch => AppErrorCode::FileNotFound,
+        git2::ErrorCode::Conflict | git2::ErrorCode::Locked | git2::ErrorCode::Modified => AppErrorCode::Conflict,
+        _ => AppErrorCode::Io,
+    };
+    AppError::new(code, error.message().to_string())
+}
+
+fn open_git_repository(path: &Path) -> Result<(Repository, PathBuf), AppError> {
+    let repository = Repository::discover(path)
+        .map_err(|_| AppError::new(AppErrorCode::FileNotFound, "Not inside a git repository"))?;
+    let workdir = repository
+        .workdir()
+        .map(canonical_path)
+        .ok_or_else(|| AppError::new(AppErrorCode::Io, "Bare repositories are not supported"))?;
+    Ok((repository, workdir))
+}
+
+fn git_relative_path(workdir: &Path, path: &Path) -> Result<PathBuf, AppError> {
+    canonical_path(path)
+        .strip_prefix(workdir)
+        .map(Path::to_path_buf)
+        .map_err(|_| AppError::new(AppErrorCode::FileNotFound, "File is outside the git repository"))
+}
+
+fn git_commit_info(commit: &git2::Commit) -> GitCommitInfo {
+    let id = commit.id().to_string();
+    let author = commit.author();
+    GitCommitInfo {
+        short_id: id.chars().take(7).collect(),
+        id,
+        summary: commit.summary().unwrap_or("").to_string(),
+        author: author.name().unwrap_or("").to_string(),
+        email: author.email().unwrap_or("").to_string(),
+        time_ms: (commit.time().seconds().max(0) as u64) * 1000,
+    }
+}
+
+fn git_blob_at(repository: &Repository, commit: &git2::Commit, relative_path: &Path) -> Option<Oid> {
+    commit
+        .tree()
+        .ok()?
+        .get_path(relative_path)
+        .ok()
+        .filter(|entry| entry.kind() == Some(git2::ObjectType::Blob))
+        .map(|entry| entry.id())
+        .filter(|id| repository.find_blob(*id).is_ok())
+}
+
+fn git_blob_text(repository: &Repository, blob_id: Oid) -> Result<String, AppError> {
+    let blob = repository.find_blob(blob_id).map_err(git_error)?;
+    String::from_utf8(blob.content().to_vec())
+        .map_err(|_| AppError::new(AppErrorCode::InvalidEncoding, "File must be UTF-8"))
+}
+
+fn git_status_label(status: Status) -> &'static str {
+    if status.is_conflicted() {
+        "conflicted"
+    } else if status.is_wt_new() && !status.is_index_new() {
+        "untracked"
+    } else if status.intersects(Status::INDEX_DELETED | Status::WT_DELETED) {
+        "deleted"
+    } else if status.intersects(Status::INDEX_RENAMED | Status::WT_RENAMED) {
+        "renamed"
+    } else if status.is_index_new() {
+        "added"
+    } else {
+        "modified"
+    }
+}
+
+fn git_status_in(folder: &Path) -> Result<GitStatusSummary, AppError> {
+    let (repository, workdir) = open_git_repository(folder)?;
+    let folder = canonical_path(folder);
+    let branch = repository
+        .head()
+        .ok()
+        .and_then(|head| head.shorthand().map(str::to_string));
+
+    let mut options = StatusOptions::new();
+    options
+        .include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .renames_head_to_index(true);
+    let statuses = repository.statuses(Some(&mut options)).map_err(git_error)?;
+
+    let mut files = Vec::new();
+    for entry in statuses.iter() {
+        let status = entry.status();
+        let Some(relative) = entry.path() else {
+            continue;
+        };
+        let path = workdir.join(relative);
+        if status.is_ignored() || !path.starts_with(&folder) {
+            continue;
+        }
+
+        files.push(GitFileStatus {
+            relative_path: path
+                .strip_prefix(&folder)
+                .unwrap_or(&path)
+                .to_string_lossy()
+                .to_string(),
+            path: path.to_string_lossy().to_string(),
+            status: git_status_label(status).to_string(),
+            staged: status.intersects(
+                Status::INDEX_NEW
+                    | Status::INDEX_MODIFIED
+                    | Status::INDEX_DELETED
+                    | Status::INDEX_RENAMED
+                    | Status::INDEX_TYPECHANGE,
+            ),
+            unstaged: status.intersects(
+                Status::WT_NEW | Status::WT_MODIFIED | Status::WT_DELETED | Status::WT_RENAMED | Status::WT_TYPECHANGE,
+            ),
+        });
+    }
+    files.sort_by_key(|file| file.relative_path.to_lowercase());
+
+    Ok(GitStatusSummary {
+        repository_root: workdir.to_string_lossy().to_string(),
+        branch,
+        files,
+    })
+}
+
+fn git_diff_head_in(document_path: &Path, content: &str) -> Result<SnapshotDiff, AppError> {
+    let (repository, workdir) = open_git_repository(document_path)?;
+    let relative = git_relative_path(&workdir, document_path)?;
+    let head_content = match repository.head().and_then(|head| head.peel_to_commit()) {
+        Ok(commit) => match git_blob_at(&repository, &commit, &relative) {
+            Some(blob_id) => git_blob_text(&repository, blob_id)?,
+            None => String::new(),
+        },
+        Err(_) => String::new(),
+    };
+    Ok(diff_texts(&head_content, content))
+}
+
+fn git_stage_in(folder: &Path, paths: &[String], stage: bool) -> Result<(), AppError> {
+    let (repository, workdir) = open_git_repository(folder)?;
+    let relative_paths = paths
+        .iter()
+        .map(|path| git_relative_path(&workdir, Path::new(path)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if !stage {
+        let head = repository.head().and_then(|head| head.peel(git2::ObjectType::Commit)).ok();
+        return repository
+            .reset_default(head.as_ref(), relative_paths.iter())
+            .map_err(git_error);
+    }
+
+    let mut index = repository.index().map_err(git_error)?;
+    for relative in &relative_paths {
+        if workdir.join(relative).exists() {
+            index.add_path(relative).map_err(git_error)?;
+        } else {
+            index.remove_path(relative).map_err(git_error)?;
+        }
+    }
+    index.write().map_err(git_error)
+}
+
+fn git_commit_in(folder: &Path, message: &str, paths: &[String]) -> Result<GitCommitInfo, AppError> {
+    if message.trim().is_empty() {
+        return Err(AppError::new(AppErrorCode::Io, "Commit message is empty"));
+    }
+    if !paths.is_empty() {
+        git_stage_in(folder, paths, true)?;
+    }
+
+    let (repository, workdir) = open_git_repository(folder)?;
+    let signature: Signature = repository.signature().map_err(|_| {
+        AppError::new(
+            AppErrorCode::Conflict,
+            "Set user.name and user.email in your git config before committing",
+        )
+    })?;
+    let mut index = repository.index().map_err(git_error)?;
+    let parent = repository.head().and_then(|head| head.peel_to_commit()).ok();
+    let tree_id = if paths.is_empty() {
+        index.write_tree().map_err(git_error)?
+    } else {
+        // Only the selected paths go into the commit; anything else already staged stays
+        // staged for a later commit.
+        let mut selected = git2::Index::new().map_err(git_error)?;
+        if let Some(parent) = &parent {
+            selected.read_tree(&parent.tree().map_err(git_error)?).map_err(git_error)?;
+        }
+        for path in paths {
+            let relative = git_relative_path(&workdir, Path::new(path))?;
+            match index.get_path(&relative, 0) {
+                Some(entry) => selected.add(&entry).map_err(git_error)?,
+                None => selected.remove_path(&relative).map_err(git_error)?,
+            }
+        }
+        selected.write_tree_to(&repository).map_err(git_error)?
+    };
+    let tree = repository.find_tree(tree_id).map_err(git_error)?;
+    let unchanged = match &parent {
+        Some(parent) => parent.tree_id() == tree_id,
+        None => tree.is_empty(),
+    };
+    if unchanged {
+        return Err(AppError::new(AppErrorCode::Conflict, "Nothing to commit"));
+    }
+
+    let parents: Vec<&git2::Commit> = parent.iter().collect();
+    let commit_id = repository
+        .commit(Some("HEAD"), &signature, &signature, message.trim(), &tree, &parents)
+        .map_err(git_error)?;
+    let commit = repository.find_commit(commit_id).map_err(git_error)?;
+    Ok(git_commit_info(&commit))
+}
+
+fn git_file_log_in(document_path: &Path, limit: usize) -> Result<Vec<GitCommitInfo>, AppError> {
+    let (repository, workdir) = open_git_repository(document_path)?;
+    let relative = git_relative_path(&workdir, document_path)?;
+    let mut walk = repository.revwalk().map_err(git_error)?;
+    if walk.push_head().is_err() {
+        return Ok(Vec::new());
+    }
+    walk.set_sorting(git2::Sort::TIME).map_err(git_error)?;
+
+    let mut commits = Vec::new();
+    for commit_id in walk {
+        if commits.len() >= limit {
+            break;
+        }
+        let commit = repository.find_commit(commit_id.map_err(git_error)?).map_err(git_error)?;
+        let Some(blob_id) = git_blob_at(&repository, &commit, &relative) else {
+            continue;
+        };
+        let changed = match commit.parent(0) {
+            Ok(parent) => git_blob_at(&repository, &parent, &relative) != Some(blob_id),
+            Err(_) => true,
+        };
+        if changed {
+            commits.push(git_commit_info(&commit));
+        }
+    }
+    Ok(commits)
+}
+
+fn git_show_revision_in(document_path: &Path, revision: &str) -> Result<GitRevisionContent, AppError> {
+    let (repository, workdir) = open_git_repository(document_path)?;
+    let relative = git_relative_path(&workdir, document_path)?;
+    let commit = repository
+        .revparse_single(revision)
+        .and_then(|object| object.peel_to_commit())
+        .map_err(git_error)?;
+    let blob_id = git_blob_at(&repository, &commit, &relative)
+        .ok_or_else(|| AppError::new(AppErrorCode::FileNotFound, "File does not exist in this revision"))?;
+
+    Ok(GitRevisionContent {
+        path: document_path.to_string_lossy().to_string(),
+        commit: git_commit_info(&commit),
+        content: git_blob_text(&repository, blob_id)?,
+    })
+}
+
+fn git_blame_in(document_path: &Path, content: Option<&str>) -> Result<Vec<GitBlameLine>, AppError> {
+    let (repository, workdir) = open_git_repository(document_path)?;
+    let relative = git_relative_path(&workdir, document_path)?;
+    let content = match content {
+        Some(content) => content.to_string(),
+        None => read_utf8(document_path)?,
+    };
+
+    let file_blame = repository.blame_file(&relative, None).ok();
+    let committed = file_blame
+        .as_ref()
+        .and_then(|blame| blame.blame_buffer(content.as_bytes()).ok());
+    let mut commits: HashMap<Oid, Option<GitCommitInfo>> = HashMap::new();
+    let line_count = content.lines().count();
+
+    let mut lines = Vec::with_capacity(line_count);
+    for line in 1..=line_count {
+        let commit_id = committed
+            .as_ref()
+            .and_then(|blame| blame.get_line(line))
+            .map(|hunk| hunk.final_commit_id())
+            .filter(|id| !id.is_zero());
+        let commit = commit_id.and_then(|id| {
+            commits
+                .entry(id)
+                .or_insert_with(|| repository.find_commit(id).ok().map(|commit| git_commit_info(&commit)))
+                .clone()
+        });
+        lines.push(GitBlameLine {
+            line: line as u32,
+            uncommitted: commit.is_none(),
+            commit,
+        });
+    }
+    Ok(lines)
+}
+
+fn split_link_and_anchor(link: &str) -> (String, Option<String>) {
+    let (path, anchor) = match link.split_once('#') {
+        Some((path, anchor)) => (path, Some(anchor)),
+        None => (link, None),
+    };
+    let path = path.split('?').next().unwrap_or(path);
+
+    (percent_decode(path), anchor.map(percent_decode))
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+
+    while index < bytes.len() {
+        let is_escape = bytes[index] == b'%'
+            && index + 2 < bytes.len()
+            && bytes[index + 1].is_ascii_hexdigit()
+            && bytes[index + 2].is_ascii_hexdigit();
+        if is_escape {
+            let hex = std::str::from_utf8(&bytes[index + 1..index + 3]).unwrap_or("0");
+            decoded.push(u8::from_str_radix(hex, 16).unwrap_or(0));
+            index += 3;
+            continue;
+        }
+        decoded.push(bytes[index]);
+        index += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+fn encode_link_path(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for ch in path.chars() {
+        match ch {
+            ' ' => encoded.push_str("%20"),
+            '%' => encoded.push_str("%25"),
+            '<' => encoded.push_str("%3C"),
+            '>' => encoded.push_str("%3E"),
+            '?' => encoded.push_str("%3F"),
+            '#' => encoded.push_str("%23"),
+            _ => encoded.push(ch),
+        }
+    }
+    encoded
+}
+
+fn unescape_markdown(value: &str) -> String {
+    let mut output = String::with_capacity(value.len());
+    let mut chars = value.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch == '\\' {
+            if let Some(next) = chars.peek().copied().filter(char::is_ascii_punctuation) {
+                output.push(next);
+                chars.next();
+                continue;
+            }
+        }
+        output.push(ch);
+    }
+    output
+}
+
+fn slugify_heading(input: &str) -> String {
+    let mut slug = String::new();
+    let mut last_dash = false;
+
+    for ch in input.chars() {
+        if ch.is_ascii_alphanumeric() {
+            slug.push(ch.to_ascii_lowercase());
+            last_dash = false;
+        } else if (ch.is_whitespace() || ch == '-' || ch == '_') && !last_dash && !slug.is_empty() {
+            slug.push('-');
+            last_dash = true;
+        }
+    }
+
+    while slug.ends_with('-') {
+        slug.pop();
+    }
+
+    slug
+}
+
+fn heading_slugs(markdown: &str) -> HashSet<String> {
+    let mut slugs = HashSet::new();
+
+    for line in markdown.lines() {
+        let trimmed = line.trim_start();
+        let hashes = trimmed.chars().take_while(|ch| *ch == '#').count();
+        if hashes == 0 || hashes > 6 {
+            continue;
+        }
+
+        let content = trimmed[hashes..].trim();
+        if content.is_empty() {
+            continue;
+        }
+
+        let slug = slugify_heading(content);
+        if !slug.is_empty() {
+            slugs.insert(slug);
+        }
+    }
+
+    slugs
+}
+
+fn normalize_link_target(raw: &str) -> String {
+    let value = raw.trim();
+    let value = if value.starts_with('<') && value.ends_with('>') && value.len() > 2 {
+        &value[1..value.len() - 1]
+    } else {
+        value.split_whitespace().next().unwrap_or("")
+    };
+
+    unescape_markdown(value).trim().to_string()
+}
+
+fn find_unescaped(input: &str, from: usize, wanted: u8, stop_at: &[u8]) -> Option<usize> {
+    let bytes = input.as_bytes();
+    let mut index = from;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'\\' => index += 1,
+            byte if byte == wanted => return Some(index),
+            byte if stop_at.contains(&byte) => return None,
+            _ => {}
+        }
+        index += 1;
+    }
+    None
+}
+
+fn skip_ascii_whitespace(input: &str, from: usize) -> usize {
+    input.as_bytes()[from..]
+        .iter()
+        .position(|byte| !byte.is_ascii_whitespace())
+        .map_or(input.len(), |offset| from + offset)
+}
+
+fn parse_inline_destination(input: &str) -> Option<(String, usize, usize)> {
+    let bytes = input.as_bytes();
+    let mut index = skip_ascii_whitespace(input, 0);
+    let mut destination_start = index;
+
+    let destination = if bytes.get(index) == Some(&b'<') {
+        let close = find_unescaped(input, index + 1, b'>', b"<\n")?;
+        let destination = &input[index + 1..close];
+        destination_start = index + 1;
+        index = close + 1;
+        destination
+    } else {
+        let start = index;
+        let mut depth = 0usize;
+        while index < bytes.len() {
+            match bytes[index] {
+                b'\\' if index + 1 < bytes.len() => index += 1,
+                byte if byte.is_ascii_whitespace() || byte.is_ascii_control() => break,
+                b'(' => depth += 1,
+                b')' if depth == 0 => break,
+                b')' => depth -= 1,
+                _ => {}
+            }
+            index += 1;
+        }
+        if depth != 0 {
+            return None;
+        }
+        &input[start..index]
+    };
+
+    index = skip_ascii_whitespace(input, index);
+    if let Some(&opening) = bytes.get(index) {
+        let closing = match opening {
+            b'"' => Some(b'"'),
+            b'\'' => Some(b'\''),
+            b'(' => Some(b')'),
+            _ => None,
+        };
+        if let Some(closing) = closing {
+            index = find_unescaped(input, index + 1, closing, b"")? + 1;
+            index = skip_ascii_whitespace(input, index);
+        }
+    }
+
+    (bytes.get(index) == Some(&b')')).then(|| (destination.to_string(), destination_start, index + 1))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LinkKind {
+    Inline,
+    Image,
+    Autolink,
+    Reference,
+    Wiki,
+    HtmlImage,
+    HtmlAnchor,
+}
+
+#[derive(Debug, Clone)]
+struct MarkdownLink {
+    line: u32,
+    column: usize,
+    target: String,
+    display: String,
+    kind: LinkKind,
+}
+
+#[derive(Debug, Clone)]
+struct ReferenceDefinition {
+    line: u32,
+    label: String,
+    target: String,
+}
+
+#[derive(Debug, Clone)]
+struct ReferenceUsage {
+    line: u32,
+    label: String,
+    display: String,
+}
+
+#[derive(Debug, Clone, Default)]
+struct ExtractedLinks {
+    links: Vec<MarkdownLink>,
+    definitions: Vec<ReferenceDefinition>,
+    references: Vec<ReferenceUsage>,
+    shortcuts: Vec<ReferenceUsage>,
+}
+
+impl LinkKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            LinkKind::Inline => "inline",
+            LinkKind::Image => "image",
+            LinkKind::Autolink => "autolink",
+            LinkKind::Reference => "reference",
+            LinkKind::Wiki => "wiki",
+            LinkKind::HtmlImage => "htmlImage",
+            LinkKind::HtmlAnchor => "htmlAnchor",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ResolvedLink {
+    link: MarkdownLink,
+    target_path: Option<PathBuf>,
+    anchor: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct IndexedDocument {
+    entry: MarkdownFileEntry,
+    content: String,
+    links: Vec<ResolvedLink>,
+}
+
+fn normalize_reference_label(label: &str) -> String {
+    label
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+fn is_fence_delimiter(line: &str) -> Option<&'static str> {
+    let trimmed = line.trim_start();
+    if trimmed.starts_with("```") {
+        Some("```")
+    } else if trimmed.starts_with("~~~") {
+        Some("~~~")
+    } else {
+        None
+    }
+}
+
+fn mask_code_spans(line: &str) -> String {
+    let mut output = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(open) = rest.find('`') {
+        let Some(close) = rest[open + 1..].find('`') else {
+            break;
+        };
+        let close = open + 1 + close;
+        output.push_str(&rest[..open]);
+        output.extend(std::iter::repeat_n(' ', close + 1 - open));
+        rest = &rest[close + 1..];
+    }
+    output.push_str(rest);
+    output
+}
+
+fn overlaps_any(spans: &[(usize, usize)], start: usize, end: usize) -> bool {
+    spans
+        .iter()
+        .any(|(span_start, span_end)| start < *span_end && *span_start < end)
+}
+
+fn wiki_link_target(inner: &str) -> Option<(String, String)> {
+    let page = inner.split('|').next().unwrap_or(inner).trim();
+    if page.is_empty() {
+        return None;
+    }
+
+    let (path_part, heading) = match page.split_once('#') {
+        Some((path_part, heading)) => (path_part.trim(), Some(heading.trim())),
+        None => (page, None),
+    };
+
+    let mut target = encode_link_path(path_part);
+    if !target.is_empty() && Path::new(path_part).extension().is_none() {
+        target.push_str(".md");
+    }
+    if let Some(heading) = heading {
+        target.push('#');
+        target.push_str(&slugify_heading(heading));
+    }
+
+    Some((target, format!("[[{}]]", page)))
+}
+
+fn extract_markdown_links(markdown: &str) -> ExtractedLinks {
+    let wiki_link_regex = Regex::new(r#"!?\[\[([^\[\]]+)\]\]"#).expect("valid regex");
+    let markdown_link_regex = Regex::new(r#"(!?)\[[^\]]*\]\("#).expect("valid regex");
+    let full_reference_regex = Regex::new(r#"!?\[([^\]]*)\]\[([^\]]*)\]"#).expect("valid regex");
+    let shortcut_reference_regex = Regex::new(r#"!?\[([^\[\]]+)\]"#).expect("valid regex");
+    let auto_link_regex = Regex::new(r#"<(https?://[^>\s]+)>"#).expect("valid regex");
+    let definition_regex =
+        Regex::new(r#"^ {0,3}\[([^\]]+)\]:\s*(<[^>]*>|\S+)"#).expect("valid regex");
+    let html_tag_regex = Regex::new(r#"(?i)<(img|a)\b[^>]*>"#).expect("valid regex");
+    let html_attr_regex =
+        Regex::new(r#"(?i)\b(src|href)\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s>]+))"#).expect("valid regex");
+
+    let mut extracted = ExtractedLinks::default();
+    let mut open_fence: Option<&'static str> = None;
+
+    for (index, raw_line) in markdown.lines().enumerate() {
+        let line_number = (index + 1) as u32;
+
+        if let Some(fence) = open_fence {
+            if raw_line.trim_start().starts_with(fence) {
+                open_fence = None;
+            }
+            continue;
+        }
+        if let Some(fence) = is_fence_delimiter(raw_line) {
+            open_fence = Some(fence);
+            continue;
+        }
+
+        let line = mask_code_spans(raw_line);
+
+        if let Some(captures) = definition_regex.captures(&line) {
+            let label = captures.get(1).map(|value| value.as_str()).unwrap_or("");
+            if let (false, Some(raw_target)) = (label.starts_with('^'), captures.get(2)) {
+                let raw = raw_target.as_str();
+                let (column, display) = match raw.strip_prefix('<').and_then(|value| value.strip_suffix('>')) {
+                    Some(inner) => (raw_target.start() + 1, inner),
+                    None => (raw_target.start(), raw),
+                };
+                let target = normalize_link_target(raw);
+                extracted.definitions.push(ReferenceDefinition {
+                    line: line_number,
+                    label: normalize_reference_label(label),
+                    target: target.clone(),
+                });
+                if !target.is_empty() {
+                    extracted.links.push(MarkdownLink {
+                        line: line_number,
+                        column,
+                        display: display.to_string(),
+                        target,
+                        kind: LinkKind::Reference,
+                    });
+                }
+                continue;
+            }
+        }
+
+        let mut claimed: Vec<(usize, usize)> = Vec::new();
+
+        for captures in wiki_link_regex.captures_iter(&line) {
+            let whole = captures.get(0).expect("match");
+            claimed.push((whole.start(), whole.end()));
+            if let Some((target, display)) = captures
+                .get(1)
+                .and_then(|value| wiki_link_target(value.as_str()))
+            {
+                extracted.links.push(MarkdownLink {
+                    line: line_number,
+                    column: whole.start() + usize::from(whole.as_str().starts_with('!')),
+                    target,
+                    display,
+                    kind: LinkKind::Wiki,
+                });
+            }
+        }
+
+        for captures in markdown_link_regex.captures_iter(&line) {
+            let whole = captures.get(0).expect("match");
+            let Some((destination, offset, consumed)) = parse_inline_destination(&line[whole.end()..]) else {
+                continue;
+            };
+            let end = whole.end() + consumed;
+            if overlaps_any(&claimed, whole.start(), end) {
+                continue;
+            }
+            claimed.push((whole.start(), end));
+
+            let target = unescape_markdown(&destination);
+            if !target.trim().is_empty() {
+                let is_image = captures.get(1).is_some_and(|bang| !bang.as_str().is_empty());
+                extracted.links.push(MarkdownLink {
+                    line: line_number,
+                    column: whole.end() + offset,
+                    target,
+                    display: destination,
+                    kind: if is_image { LinkKind::Image } else { LinkKind::Inline },
+                });
+            }
+        }
+
+        for captures in full_reference_regex.captures_iter(&line) {
+            let whole = captures.get(0).expect("match");
+            if overlaps_any(&claimed, whole.start(), whole.end()) {
+                continue;
+            }
+            claimed.push((whole.start(), whole.end()));
+            let text = captures.get(1).map(|value| value.as_str()).unwrap_or("");
+            let label = captures.get(2).map(|value| value.as_str()).unwrap_or("");
+            let label = if label.trim().is_empty() { text } else { label };
+            if label.trim().is_empty() {
+                continue;
+            }
+            extracted.references.push(ReferenceUsage {
+                line: line_number,
+                label: normalize_reference_label(label),
+                display: whole.as_str().to_string(),
+            });
+        }
+
+        for captures in shortcut_reference_regex.captures_iter(&line) {
+            let whole = captures.get(0).expect("match");
+            if overlaps_any(&claimed, whole.start(), whole.end()) {
+                continue;
+            }
+            let label = captures.get(1).map(|value| value.as_str()).unwrap_or("");
+            let next_char = line[whole.end()..].chars().next();
+            if label.starts_with('^') || matches!(next_char, Some('(') | Some('[') | Some(':')) {
+                continue;
+            }
+            extracted.shortcuts.push(ReferenceUsage {
+                line: line_number,
+                label: normalize_reference_label(label),
+                display: whole.as_str().to_string(),
+            });
+        }
+
+        for captures in auto_link_regex.captures_iter(&line) {
+            if let Some(target) = captures.get(1) {
+                extracted.links.push(MarkdownLink {
+                    line: line_number,
+                    column: target.start(),
+                    target: target.as_str().to_string(),
+                    display: target.as_str().to_string(),
+                    kind: LinkKind::Autolink,
+                });
+            }
+        }
+
+        for tag in html_tag_regex.captures_iter(&line) {
+            let tag_name = tag.get(1).map(|value| value.as_str()).unwrap_or("");
+            let is_image = tag_name.eq_ignore_ascii_case("img");
+            let wanted_attr = if is_image { "src" } else { "href" };
+            let Some(tag_match) = tag.get(0) else {
+                continue;
+            };
+            for attr in html_attr_regex.captures_iter(tag_match.as_str()) {
+                let name = attr.get(1).map(|value| value.as_str()).unwrap_or("");
+                if !name.eq_ignore_ascii_case(wanted_attr) {
+                    continue;
+                }
+                let Some(value_match) = attr.get(2).or_else(|| attr.get(3)).or_else(|| attr.get(4)) else {
+                    continue;
+                };
+                let raw = value_match.as_str();
+                let value = raw.trim().to_string();
+                if !value.is_empty() {
+                    extracted.links.push(MarkdownLink {
+                        line: line_number,
+                        column: tag_match.start() + value_match.start() + (raw.len() - raw.trim_start().len()),
+                        display: value.clone(),
+                        target: value,
+                        kind: if is_image { LinkKind::HtmlImage } else { LinkKind::HtmlAnchor },
+                    });
+                }
+            }
+        }
+    }
+
+    extracted
+}
+
+fn parse_external_host_port(url: &str) -> Option<(String, u16)> {
+    let (scheme, rest) = url.split_once("://")?;
+    let authority = rest
+        .split('/')
+        .next()
+        .unwrap_or(rest)
+        .split('?')
+        .next()
+        .unwrap_or(rest)
+        .split('#')
+        .next()
+        .unwrap_or(rest);
+
+    let authority = authority.rsplit('@').next().unwrap_or(authority);
+
+    let default_port = if scheme.eq_ignore_ascii_case("https") {
+        443
+    } else {
+        80
+    };
+
+    if authority.starts_with('[') {
+        let end = authority.find(']')?;
+        let host = authority[1..end].to_string();
+        let port = authority[end + 1..]
+            .strip_prefix(':')
+            .and_then(|value| value.parse::<u16>().ok())
+            .unwrap_or(default_port);
+        return Some((host, port));
+    }
+
+    if let Some((host, port)) = authority.rsplit_once(':') {
+        if let Ok(parsed_port) = port.parse::<u16>() {
+            return Some((host.to_string(), parsed_port));
+        }
+    }
+
+    Some((authority.to_string(), default_port))
+}
+
+const MAX_EXTERNAL_REDIRECTS: usize = 5;
+
+fn resolve_redirect_location(base: &str, location: &str) -> String {
+    if location.contains("://") {
+        return location.to_string();
+    }
+
+    let (scheme, rest) = base.split_once("://").unwrap_or(("https", base));
+    if let Some(stripped) = location.strip_prefix("//") {
+        return format!("{scheme}://{stripped}");
+    }
+
+    let authority_end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    let authority = &rest[..authority_end];
+    if location.starts_with('/') {
+        return format!("{scheme}://{authority}{location}");
+    }
+
+    let path = rest[authority_end..]
+        .split(['?', '#'])
+        .next()
+        .unwrap_or("");
+    let directory = match path.rfind('/') {
+        Some(index) => &path[..=index],
+        None => "/",
+    };
+    format!("{scheme}://{authority}{directory}{location}")
+}
+
+fn external_check_agent(options: &ExternalCheckOptions) -> ureq::Agent {
+    ureq::Agent::config_builder()
+        .timeout_global(Some(Duration::from_millis(options.timeout_ms.max(1))))
+        .max_redirects(0)
+        .http_status_as_error(false)
+        .user_agent("Md Editor link checker")
+        .build()
+        .into()
+}
+
+fn fetch_external_status(agent: &ureq::Agent, url: &str) -> Result<(u16, Option<String>), String> {
+    let response = match agent.head(url).call() {
+        Ok(response) if !matches!(response.status().as_u16(), 403 | 405 | 501) => response,
+        _ => agent.get(url).call().map_err(|error| error.to_string())?,
+    };
+
+    let location = response
+        .headers()
+        .get("location")
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string());
+    Ok((response.status().as_u16(), location))
+}
+
+fn check_external_url(agent: &ureq::Agent, url: &str, checked_at_ms: u64) -> ExternalLinkStatus {
+    let mut current = url.to_string();
+
+    for _ in 0..=MAX_EXTERNAL_REDIRECTS {
+        let (status_code, location) = match fetch_external_status(agent, &current) {
+            Ok(result) => result,
+            Err(error) => {
+                return ExternalLinkStatus {
+                    status_code: None,
+                    final_url: (current != url).then_some(current),
+                    error: Some(error),
+                    checked_at_ms,
+                };
+            }
+        };
+
+        match location {
+            Some(location) if (300..400).contains(&status_code) => {
+                current = resolve_redirect_location(&current, &location);
+            }
+            _ => {
+                return ExternalLinkStatus {
+                    status_code: Some(status_code),
+                    final_url: (current != url).then_some(current),
+                    error: None,
+                    checked_at_ms,
+                };
+            }
+        }
+    }
+
+    ExternalLinkStatus {
+        status_code: None,
+        final_url: Some(current),
+        error: Some("Too many redirects".to_string()),
+        checked_at_ms,
+    }
+}
+
+struct HostRateLimiter {
+    interval: Duration,
+    next_slot: Mutex<HashMap<String, Instant>>,
+}
+
+impl HostRateLimiter {
+    fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            next_slot: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn wait_for(&self, url: &str) {
+        let host = parse_external_host_port(url)
+            .map(|(host, _)| host.to_ascii_lowercase())
+            .unwrap_or_default();
+        let slot = {
+            let mut next_slot = match self.next_slot.lock() {
+                Ok(guard) => guard,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            let now = Instant::now();
+            let slot = next_slot.get(&host).copied().unwrap_or(now).max(now);
+            next_slot.insert(host, slot + self.interval);
+            slot
+        };
+
+        let now = Instant::now();
+        if slot > now {
+            std::thread::sleep(slot - now);
+        }
+    }
+}
+
+fn load_external_link_cache(path: &Path) -> ExternalLinkCache {
+    read_utf8(path)
+        .ok()
+        .and_then(|raw| serde_json::from_str::<ExternalLinkCache>(&raw).ok())
+        .unwrap_or_default()
+}
+
+fn check_external_urls(
+    urls: &[String],
+    options: &ExternalCheckOptions,
+    cache_path: Option<&Path>,
+) -> Result<HashMap<String, ExternalLinkStatus>, AppError> {
+    let now = now_ms()?;
+    let mut cache = cache_path
+        .map(load_external_link_cache)
+        .unwrap_or_default();
+    cache
+        .entries
+        .retain(|_, status| now.saturating_sub(status.checked_at_ms) < options.cache_ttl_ms);
+
+    let mut results = HashMap::new();
+    let mut pending = Vec::new();
+    for url in urls {
+        if results.contains_key(url) || pending.contains(url) {
+            continue;
+        }
+        match cache.entries.get(url) {
+            Some(status) => {
+                results.insert(url.clone(), status.clone());
+            }
+            None => pending.push(url.clone()),
+        }
+    }
+
+    if !pending.is_empty() {
+        let agent = external_check_agent(options);
+        let limiter = HostRateLimiter::new(Duration::from_millis(options.per_host_interval_ms));
+        let workers = (options.concurrency.max(1) as usize).min(pending.len());
+        let queue = Mutex::new(pending);
+        let checked = Mutex::new(Vec::new());
+
+        std::thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| loop {
+                    let next = match queue.lock() {
+                        Ok(mut queue) => queue.pop(),
+                        Err(_) => None,
+                    };
+                    let Some(url) = next else {
+                        break;
+                    };
+                    limiter.wait_for(&url);
+                    let status = check_external_url(&agent, &url, now);
+                    if let Ok(mut checked) = checked.lock() {
+                        checked.push((url, status));
+                    }
+                });
+            }
+        });
+
+        for (url, status) in checked.into_inner().unwrap_or_default() {
+            // Transport failures are usually transient, so they are re-checked next time.
+            if status.error.is_none() {
+                cache.entries.insert(url.clone(), status.clone());
+            }
+            results.insert(url, status);
+        }
+    }
+
+    if let Some(path) = cache_path {
+        let serialized = serde_json::to_string(&cache)
+            .map_err(|error| AppError::new(AppErrorCode::Io, error.to_string()))?;
+        atomic_write(path, &serialized)?;
+    }
+
+    Ok(results)
+}
+
+fn external_status_issue(line: u32, link: &str, status: &ExternalLinkStatus) -> Option<LinkValidationIssue> {
+    let (severity, message) = match (status.status_code, &status.error) {
+        (_, Some(error)) => ("warning", format!("External URL could not be checked: {error}")),
+        (Some(404 | 410), _) => ("error", "External URL returned a missing page".to_string()),
+        (Some(code), _) if code >= 400 => ("warning", format!("External URL returned HTTP {code}")),
+        (Some(_), _) if status.final_url.is_some() => ("warning", "External URL redirects".to_string()),
+        _ => return None,
+    };
+
+    let mut issue = LinkValidationIssue::new(line, link, severity, message);
+    issue.status_code = status.status_code;
+    issue.redirect_target = status.final_url.clone();
+    Some(issue)
+}
+
+fn edit_distance(left: &str, right: &str) -> usize {
+    let right_chars: Vec<char> = right.chars().collect();
+    let mut previous: Vec<usize> = (0..=right_chars.len()).collect();
+    let mut current = vec![0usize; right_chars.len() + 1];
+
+    for (left_index, left_char) in left.chars().enumerate() {
+        current[0] = left_index + 1;
+        for (right_index, right_char) in right_chars.iter().enumerate() {
+            let substitution = previous[right_index] + usize::from(left_char != *right_char);
+            current[right_index + 1] = substitution
+                .min(previous[right_index + 1] + 1)
+                .min(current[right_index] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[right_chars.len()]
+}
+
+fn is_close_match(query: &str, distance: usize) -> bool {
+    distance <= (query.chars().count() / 3).max(2)
+}
+
+fn collect_workspace_paths(
+    current: &Path,
+    files: &mut Vec<PathBuf>,
+    visited_dirs: &mut HashSet<PathBuf>,
+) -> Result<(), AppError> {
+    let canonical_current = fs::canonicalize(current).map_err(|error| map_io_error(&error))?;
+    if !visited_dirs.insert(canonical_current) {
+        return Ok(());
+    }
+
+    let entries = fs::read_dir(current).map_err(|error| map_io_error(&error))?;
+    for entry_result in entries {
+        let entry = entry_result.map_err(|error| map_io_error(&error))?;
+        let path = entry.path();
+        let file_type = entry.file_type().map_err(|error| map_io_error(&error))?;
+
+        if file_type.is_symlink() && path.is_dir() {
+            continue;
+        }
+
+        if path.is_dir() {
+            if !should_skip_dir(&path) {
+                collect_workspace_paths(&path, files, visited_dirs)?;
+            }
+        } else if path.is_file() {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+fn case_mismatch_path(path: &Path) -> Option<PathBuf> {
+    let parent = path.parent()?;
+    let name = path.file_name()?.to_str()?;
+
+    let names: Vec<String> = fs::read_dir(parent)
+        .ok()?
+        .filter_map(Result::ok)
+        .filter_map(|entry| entry.file_name().to_str().map(str::to_string))
+        .collect();
+    if names.iter().any(|actual| actual == name) {
+        return None;
+    }
+    names
+        .into_iter()
+        .find(|actual| actual.eq_ignore_ascii_case(name))
+        .map(|actual| parent.join(actual))
+}
+
+fn closest_workspace_file(target_path: &Path, candidates: &[PathBuf]) -> Option<PathBuf> {
+    let wanted = target_path.file_name()?.to_str()?.to_lowercase();
+    let wanted_extension = ext_from_path(target_path);
+
+    candidates
+        .iter()
+        .filter_map(|candidate| {
+            let name = candidate.file_name()?.to_str()?.to_lowercase();
+            let distance = edit_distance(&wanted, &name);
+            if !is_close_match(&wanted, distance) {
+                return None;
+            }
+            let extension_penalty = usize::from(ext_from_path(candidate) != wanted_extension);
+            Some((distance + extension_penalty, candidate.components().count(), candidate))
+        })
+        .min_by(|left, right| (left.0, left.1, left.2).cmp(&(right.0, right.1, right.2)))
+        .map(|(_, _, candidate)| candidate.clone())
+}
+
+fn closest_heading_slug(anchor: &str, slugs: &HashSet<String>) -> Option<String> {
+    slugs
+        .iter()
+        .map(|slug| (edit_distance(anchor, slug), slug))
+        .filter(|(distance, _)| is_close_match(anchor, *distance))
+        .min()
+        .map(|(_, slug)| slug.clone())
+}
+
+fn resolve_link_path(document_path: &Path, workspace_root: &Path, path_part: &str) -> PathBuf {
+    if path_part.is_empty() {
+        return document_path.to_path_buf();
+    }
+
+    match path_part.strip_prefix('/') {
+        Some(rooted) => workspace_root.join(rooted),
+        None => document_path
+            .parent()
+            .map(|parent| parent.join(path_part))
+            .unwrap_or_else(|| PathBuf::from(path_part)),
+    }
+}
+
+fn relative_link_path(from_dir: &Path, target: &Path) -> String {
+    let from: Vec<_> = from_dir.components().collect();
+    let to: Vec<_> = target.components().collect();
+    let common = from
+        .iter()
+        .zip(to.iter())
+        .take_while(|(left, right)| left == right)
+        .count();
+
+    let mut parts: Vec<String> = vec!["..".to_string(); from.len() - common];
+    parts.extend(
+        to[common..]
+            .iter()
+            .map(|component| component.as_os_str().to_string_lossy().to_string()),
+    );
+    parts.join("/")
+}
+
+fn rebuild_link_target(
+    link: &MarkdownLink,
+    original_path: &str,
+    new_path: &str,
+    anchor: Option<&str>,
+) -> String {
+    let mut target = if link.kind == LinkKind::Wiki {
+        let had_extension = link
+            .display
+            .trim_start_matches("[[")
+            .trim_end_matches("]]")
+            .split('#')
+            .next()
+            .is_some_and(|page| Path::new(page).extension().is_some());
+        if had_extension {
+            new_path.to_string()
+        } else {
+            new_path.strip_suffix(".md").unwrap_or(new_path).to_string()
+        }
+    } else {
+        let encoded = encode_link_path(new_path);
+        if original_path.starts_with("./") && !encoded.starts_with('.') {
+            format!("./{encoded}")
+        } else {
+            encoded
+        }
+    };
+
+    if let Some(anchor) = anchor {
+        target.push('#');
+        target.push_str(anchor);
+    }
+
+    if link.kind == LinkKind::Wiki {
+        format!("[[{target}]]")
+    } else {
+        target
+    }
+}
+
+fn replace_link_in_line(line: &str, link: &str, replacement: &str) -> Option<String> {
+    let wiki_parts = link
+        .strip_prefix("[[")
+        .and_then(|value| value.strip_suffix("]]"))
+        .zip(
+            replacement
+                .strip_prefix("[[")
+                .and_then(|value| value.strip_suffix("]]")),
+        );
+
+    if let Some((page, new_page)) = wiki_parts {
+        let needle = format!("[[{page}");
+        let mut search_from = 0;
+        while let Some(found) = line[search_from..].find(&needle) {
+            let start = search_from + found;
+            let after = start + needle.len();
+            if line[after..].starts_with("]]") || line[after..].starts_with('|') {
+                return Some(format!("{}[[{}{}", &line[..start], new_page, &line[after..]));
+            }
+            search_from = after;
+        }
+        return None;
+    }
+
+    let start = line.find(link)?;
+    Some(format!("{}{}{}", &line[..start], replacement, &line[start + link.len()..]))
+}
+
+fn normalize_lexical_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                if !normalized.pop() {
+                    normalized.push("..");
+                }
+            }
+            other => normalized.push(other.as_os_str()),
+        }
+    }
+    normalized
+}
+
+fn resolve_document_links(document_path: &Path, workspace_root: &Path, markdown: &str) -> Vec<ResolvedLink> {
+    extract_markdown_links(markdown)
+        .links
+        .into_iter()
+        .map(|link| {
+            if link.target.trim().is_empty() || is_ignored_link(&link.target) || is_external_link(&link.target) {
+                return ResolvedLink {
+                    link,
+                    target_path: None,
+                    anchor: None,
+                };
+            }
+
+            let (path_part, anchor) = split_link_and_anchor(&link.target);
+            let target_path = resolve_link_path(document_path, workspace_root, &path_part);
+            ResolvedLink {
+                link,
+                target_path: Some(normalize_lexical_path(&target_path)),
+                anchor,
+            }
+        })
+        .collect()
+}
+
+fn index_workspace_links(workspace_root: &Path) -> Result<Vec<IndexedDocument>, AppError> {
+    let mut files = Vec::new();
+    let mut visited_dirs = HashSet::new();
+    collect_workspace_text_files(workspace_root, workspace_root, &mut files, &mut visited_dirs)?;
+    files.sort_by_key(|entry| entry.relative_path.to_lowercase());
+
+    let mut documents = Vec::new();
+    for entry in files {
+        let path = PathBuf::from(&entry.path);
+        let content = match read_utf8(&path) {
+            Ok(content) => content,
+            Err(_) => continue,
+        };
+        let links = resolve_document_links(&path, workspace_root, &content);
+        documents.push(IndexedDocument {
+            entry,
+            content,
+            links,
+        });
+    }
+
+    Ok(documents)
+}
+
+#[derive(Debug, Clone)]
+struct PendingRewrite {
+    path: PathBuf,
+    relative_path: String,
+    line: u32,
+    column: usize,
+    old_link: String,
+    new_link: String,
+}
+
+fn retarget_link(link: &MarkdownLink, original_path: &str, new_path: &str) -> String {
+    let rebuilt = rebuild_link_target(link, original_path, new_path, None);
+    if link.kind == LinkKind::Wiki {
+        let inner = link.display.trim_start_matches("[[").trim_end_matches("]]");
+        let heading = inner.find('#').map(|index| &inner[index..]).unwrap_or("");
+        format!("{}{}]]", rebuilt.trim_end_matches("]]"), heading)
+    } else {
+        let suffix = link
+            .display
+            .find(['?', '#'])
+            .map(|index| &link.display[index..])
+            .unwrap_or("");
+        format!("{rebuilt}{suffix}")
+    }
+}
+
+fn apply_rewrites_to_content(content: &str, rewrites: &[&PendingRewrite]) -> String {
+    let mut lines: Vec<String> = content.split_inclusive('\n').map(str::to_string).collect();
+    let mut ordered: Vec<&&PendingRewrite> = rewrites.iter().collect();
+    ordered.sort_by_key(|rewrite| std::cmp::Reverse((rewrite.line, rewrite.column)));
+
+    for rewrite in ordered {
+        let Some(line) = lines.get_mut(rewrite.line.saturating_sub(1) as usize) else {
+            continue;
+        };
+        let (old_text, new_text) = if rewrite.old_link.starts_with("[[") {
+            (
+                rewrite.old_link.trim_end_matches("]]"),
+                rewrite.new_link.trim_end_matches("]]"),
+            )
+        } else {
+            (rewrite.old_link.as_str(), rewrite.new_link.as_str())
+        };
+
+        let at_column = line
+            .get(rewrite.column..)
+            .is_some_and(|rest| rest.starts_with(old_text));
+        if at_column {
+            line.replace_range(rewrite.column..rewrite.column + old_text.len(), new_text);
+        } else if let Some(updated) = replace_link_in_line(line, &rewrite.old_link, &rewrite.new_link) {
+            *line = updated;
+        }
+    }
+
+    lines.concat()
+}
+
+fn plan_document_rename(
+    workspace_root: &Path,
+    old_path: &Path,
+    new_path: &Path,
+) -> Result<(Vec<IndexedDocument>, Vec<PendingRewrite>), AppError> {
+    let old_key = normalize_lexical_path(old_path);
+    let new_key = normalize_lexical_path(new_path);
+    let documents = index_workspace_links(workspace_root)?;
+    let mut rewrites = Vec::new();
+
+    for document in &documents {
+        let source = normalize_lexical_path(Path::new(&document.entry.path));
+        let is_moved = source == old_key;
+        let source_dir_after = if is_moved {
+            new_key.parent().unwrap_or(workspace_root).to_path_buf()
+        } else {
+            source.parent().unwrap_or(workspace_root).to_path_buf()
+        };
+
+        for resolved in &document.links {
+            let Some(target) = &resolved.target_path else {
+                continue;
+            };
+            let (path_part, _) = split_link_and_anchor(&resolved.link.target);
+            if path_part.is_empty() {
+                continue;
+            }
+
+            let new_target = if *target == old_key {
+                new_key.clone()
+            } else if is_moved && !path_part.starts_with('/') {
+                target.clone()
+            } else {
+                continue;
+            };
+
+            let new_relative = if path_part.starts_with('/') {
+                format!("/{}", relative_link_path(workspace_root, &new_target))
+            } else {
+                relative_link_path(&source_dir_after, &new_target)
+            };
+            let new_link = retarget_link(&resolved.link, &path_part, &new_relative);
+            if new_link == resolved.link.display {
+                continue;
+            }
+
+            rewrites.push(PendingRewrite {
+                path: PathBuf::from(&document.entry.path),
+                relative_path: document.entry.relative_path.clone(),
+                line: resolved.link.line,
+                column: resolved.link.column,
+                old_link: resolved.link.display.clone(),
+                new_link,
+            });
+        }
+    }
+
+    Ok((documents, rewrites))
+}
+
+fn rename_plan_dto(old_path: &Path, new_path: &Path, rewrites: &[PendingRewrite]) -> DocumentRenamePlan {
+    DocumentRenamePlan {
+        old_path: old_path.to_string_lossy().to_string(),
+        new_path: new_path.to_string_lossy().to_string(),
+        rewrites: rewrites
+            .iter()
+            .map(|rewrite| LinkRewrite {
+                path: rewrite.path.to_string_lossy().to_string(),
+                relative_path: rewrite.relative_path.clone(),
+                line: rewrite.line,
+                old_link: rewrite.old_link.clone(),
+                new_link: rewrite.new_link.clone(),
+            })
+            .collect(),
+    }
+}
+
+type FileOriginals = Vec<(PathBuf, Option<Vec<u8>>)>;
+
+fn write_files_atomically(writes: &[(PathBuf, String)]) -> Result<FileOriginals, AppError> {
+    let mut staged: Vec<(PathBuf, PathBuf)> = Vec::new();
+    for (path, content) in writes {
+        let parent = path
+            .parent()
+            .ok_or_else(|| AppError::new(AppErrorCode::Io, "Missing parent directory"))?;
+        let file_name = path
+            .file_name()
+            .and_then(|value| value.to_str())
+            .unwrap_or("document.md");
+        let temp_path = parent.join(format!(".{}.{}.staged", file_name, std::process::id()));
+
+        let staged_write = fs::create_dir_all(parent)
+            .and_then(|_| fs::write(&temp_path, content.as_bytes()))
+            .map_err(|error| map_io_error(&error));
+        if let Err(error) = staged_write {
+            let _ = fs::remove_file(&temp_path);
+            for (_, temp) in &staged {
+                let _ = fs::remove_file(temp);
+            }
+            return Err(error);
+        }
+        staged.push((path.clone(), temp_path));
+    }
+
+    let originals: FileOriginals = staged
+        .iter()
+        .map(|(path, _)| (path.clone(), fs::read(path).ok()))
+        .collect();
+    for (index, (path, temp_path)) in staged.iter().enumerate() {
+        if let Err(error) = fs::rename(temp_path, path) {
+            restore_files(&originals[..index]);
+            for (_, remaining) in staged.iter().skip(index) {
+                let _ = fs::remove_file(remaining);
+            }
+            return Err(map_io_error(&error));
+        }
+    }
+
+    Ok(originals)
+}
+
+fn restore_files(originals: &[(PathBuf, Option<Vec<u8>>)]) {
+    for (path, original) in originals {
+        match original {
+            Some(original) => {
+                let _ = atomic_write_bytes(path, original);
+            }
+            None => {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+}
+
+fn heading_text_slug(heading: &str) -> String {
+    slugify_heading(heading.trim_start().trim_start_matches('#'))
+}
+
+fn rename_heading_line(markdown: &str, old_slug: &str, new_heading: &str) -> Option<String> {
+    let new_text = new_heading.trim().trim_start_matches('#').trim();
+    let mut renamed = String::with_capacity(markdown.len());
+    let mut found = false;
+
+    for line in markdown.split_inclusive('\n') {
+        let body = line.trim_end_matches(['\n', '\r']);
+        let indent = body.len() - body.trim_start().len();
+        let hashes = body[indent..].chars().take_while(|ch| *ch == '#').count();
+        let is_match = !found
+            && (1..=6).contains(&hashes)
+            && slugify_heading(body[indent + hashes..].trim()) == old_slug;
+        if !is_match {
+            renamed.push_str(line);
+            continue;
+        }
+
+        found = true;
+        renamed.push_str(&body[..indent + hashes]);
+        renamed.push(' ');
+        renamed.push_str(new_text);
+        renamed.push_str(&line[body.len()..]);
+    }
+
+    found.then_some(renamed)
+}
+
+fn plan_heading_rename(
+    workspace_root: &Path,
+    document_path: &Path,
+    markdown: Option<&str>,
+    old_slug: &str,
+    new_heading: &str,
+) -> Result<(Vec<IndexedDocument>, Vec<PendingRewrite>), AppError> {
+    let document_key = normalize_lexical_path(document_path);
+    let new_slug = heading_text_slug(new_heading);
+    let mut documents = index_workspace_links(workspace_root)?;
+
+    if let Some(markdown) = markdown {
+        documents.retain(|document| normalize_lexical_path(Path::new(&document.entry.path)) != document_key);
+        documents.push(IndexedDocument {
+            entry: MarkdownFileEntry {
+                path: document_path.to_string_lossy().to_string(),
+                name: document_path
+                    .file_name()
+                    .and_then(|value| value.to_str())
+                    .unwrap_or("untitled.md")
+                    .to_string(),
+                relative_path: document_path
+                    .strip_prefix(workspace_root)
+                    .unwrap_or(document_path)
+                    .to_string_lossy()
+                    .to_string(),
+            },
+            content: markdown.to_string(),
+            links: resolve_document_links(document_path, workspace_root, markdown),
+        });
+    }
+
+    let mut rewrites = Vec::new();
+    for document in &documents {
+        for resolved in &document.links {
+            let points_at_heading = resolved.target_path.as_ref() == Some(&document_key)
+                && resolved
+                    .anchor
+                    .as_deref()
+                    .is_some_and(|anchor| anchor.to_ascii_lowercase() == old_slug);
+            if !points_at_heading {
+                continue;
+            }
+
+            let display = &resolved.link.display;
+            let new_link = if resolved.link.kind == LinkKind::Wiki {
+                let page = display
+                    .trim_start_matches("[[")
+                    .split('#')
+                    .next()
+                    .unwrap_or("");
+                format!("[[{}#{}]]", page, new_heading.trim().trim_start_matches('#').trim())
+            } else {
+                match display.find('#') {
+                    Some(index) => format!("{}{}", &display[..=index], new_slug),
+                    None => continue,
+                }
+            };
+            if new_link == *display {
+                continue;
+            }
+
+            rewrites.push(PendingRewrite {
+                path: PathBuf::from(&document.entry.path),
+                relative_path: document.entry.relative_path.clone(),
+                line: resolved.link.line,
+                column: resolved.link.column,
+                old_link: display.clone(),
+                new_link,
+            });
+        }
+    }
+
+    Ok((documents, rewrites))
+}
+
+fn line_context(content: &str, line: u32, needle: &str) -> String {
+    let text = content.lines().nth(line.saturating_sub(1) as usize).unwrap_or("");
+    let position = text.find(needle).unwrap_or(0);
+    build_snippet(text, position)
+}
+
+fn find_orphaned_assets_in(workspace_root: &Path, settings: &AssetSettings) -> Result<Vec<OrphanedAsset>, AppError> {
+    let referenced: HashSet<PathBuf> = index_workspace_links(workspace_root)?
+        .iter()
+        .flat_map(|document| document.links.iter())
+        .filter_map(|resolved| resolved.target_path.as_deref().map(normalize_lexical_path))
+        .collect();
+
+    let mut files = Vec::new();
+    let mut visited_dirs = HashSet::new();
+    collect_workspace_paths(workspace_root, &mut files, &mut visited_dirs)?;
+
+    let mut orphans = Vec::new();
+    for file in files {
+        let relative = file.strip_prefix(workspace_root).unwrap_or(&file).to_path_buf();
+        let in_assets = is_asset_location(&relative, settings);
+        let hidden = file
+            .file_name()
+            .and_then(|value| value.to_str())
+            .is_some_and(|name| name.starts_with('.'));
+        if !in_assets || hidden || referenced.contains(&normalize_lexical_path(&file)) {
+            continue;
+        }
+
+        let metadata = fs::metadata(&file).map_err(|error| map_io_error(&error))?;
+        orphans.push(OrphanedAsset {
+            path: file.to_string_lossy().to_string(),
+            relative_path: relative.to_string_lossy().to_string(),
+            size_bytes: metadata.len(),
+            modified_ms: modified_ms(&file)?,
+        });
+    }
+    orphans.sort_by_key(|asset| asset.relative_path.to_lowercase());
+    Ok(orphans)
+}
+
+fn orphaned_assets_to_trash(
+    workspace_root: &Path,
+    settings: &AssetSettings,
+    paths: &[String],
+) -> Result<Vec<PathBuf>, AppError> {
+    let orphans: HashSet<PathBuf> = find_orphaned_assets_in(workspace_root, settings)?
+        .into_iter()
+        .map(|asset| normalize_lexical_path(Path::new(&asset.path)))
+        .collect();
+
+    paths
+        .iter()
+        .map(|path| {
+            let normalized = normalize_lexical_path(Path::new(path));
+            if orphans.contains(&normalized) {
+                Ok(normalized)
+            } else {
+                Err(AppError::new(
+                    AppErrorCode::Conflict,
+                    format!("{} is referenced or is not an asset", path),
+                ))
+            }
+        })
+        .collect()
+}
+
+fn require_workspace_folder(workspace_folder: &str) -> Result<PathBuf, AppError> {
+    let folder_path = PathBuf::from(workspace_folder);
+    if !folder_path.is_dir() {
+        return Err(AppError::new(
+            AppErrorCode::FileNotFound,
+            "Folder does not exist",
+        ));
+    }
+    Ok(folder_path)
+}
+
+fn is_external_link(link: &str) -> bool {
+    link.starts_with("http://") || link.starts_with("https://")
+}
+
+fn is_ignored_link(link: &str) -> bool {
+    link.starts_with("mailto:") || link.starts_with("tel:") || link.starts_with("javascript:")
+}
+
+#[tauri::command]
 pub fn open_document(path: String) -> Result<OpenDocumentResult, AppError> {
     let file_path = PathBuf::from(path);
     if !file_path.exists() {
         append_log("open_document_failed", "file not found");
         return Err(AppError::new(
             AppErrorCode::FileNotFound,
-            "Document does not exist",
+            "Document does not exist",
+        ));
+    }
+
+    let content = read_utf8(&file_path)?;
+    let mtime_ms = modified_ms(&file_path)?;
+    append_log("open_document", &file_path.to_string_lossy());
+
+    Ok(OpenDocumentResult {
+        path: file_path.to_string_lossy().to_string(),
+        content,
+        mtime_ms,
+    })
+}
+
+#[tauri::command]
+pub fn save_document(
+    path: String,
+    content: String,
+    expected_mtime_ms: Option<u64>,
+) -> Result<SaveResult, AppError> {
+    let file_path = PathBuf::from(path);
+    if !file_path.exists() {
+        append_log("save_document_failed", "file not found");
+        return Err(AppError::new(
+            AppErrorCode::FileNotFound,
+            "Cannot save because file no longer exists",
+        ));
+    }
+
+    if let Some(expected) = expected_mtime_ms {
+        let current = modified_ms(&file_path)?;
+        if current != expected {
+            append_log("save_document_failed", "mtime conflict");
+            return Err(AppError::new(
+                AppErrorCode::Conflict,
+                "File changed on disk. Reopen or Save As to avoid overwriting.",
+            ));
+        }
+    }
+
+    atomic_write(&file_path, &content)?;
+    let mtime_ms = modified_ms(&file_path)?;
+    append_log("save_document", &file_path.to_string_lossy());
+
+    Ok(SaveResult {
+        path: file_path.to_string_lossy().to_string(),
+        mtime_ms,
+        saved_at_ms: now_ms()?,
+    })
+}
+
+#[tauri::command]
+pub fn save_as_document(path: String, content: String) -> Result<SaveResult, AppError> {
+    let file_path = PathBuf::from(path);
+    atomic_write(&file_path, &content)?;
+    append_log("save_as_document", &file_path.to_string_lossy());
+
+    Ok(SaveResult {
+        path: file_path.to_string_lossy().to_string(),
+        mtime_ms: modified_ms(&file_path)?,
+        saved_at_ms: now_ms()?,
+    })
+}
+
+#[tauri::command]
+pub fn write_text_file(path: String, content: String) -> Result<SaveResult, AppError> {
+    let file_path = PathBuf::from(path);
+    atomic_write(&file_path, &content)?;
+    append_log("write_text_file", &file_path.to_string_lossy());
+
+    Ok(SaveResult {
+        path: file_path.to_string_lossy().to_string(),
+        mtime_ms: modified_ms(&file_path)?,
+        saved_at_ms: now_ms()?,
+    })
+}
+
+#[tauri::command]
+pub fn load_recovery_draft() -> Result<Option<String>, AppError> {
+    let path = recovery_draft_path()?;
+    append_log("load_recovery_draft", &path.to_string_lossy());
+    load_recovery_draft_from_path(&path)
+}
+
+#[tauri::command]
+pub fn store_recovery_draft(content: String) -> Result<(), AppError> {
+    let path = recovery_draft_path()?;
+    store_recovery_draft_at_path(&path, &content)?;
+    append_log(
+        "store_recovery_draft",
+        if content.trim().is_empty() { "clear" } else { "write" },
+    );
+    Ok(())
+}
+
+#[tauri::command]
+pub fn list_markdown_files(directory: String) -> Result<Vec<MarkdownFileEntry>, AppError> {
+    let folder_path = PathBuf::from(directory);
+    if !folder_path.exists() || !folder_path.is_dir() {
+        append_log("list_markdown_files_failed", "directory missing");
+        return Err(AppError::new(
+            AppErrorCode::FileNotFound,
+            "Folder does not exist",
+        ));
+    }
+
+    let mut files = Vec::new();
+    let mut visited_dirs = HashSet::new();
+    collect_workspace_text_files(&folder_path, &folder_path, &mut files, &mut visited_dirs)?;
+    files.sort_by_key(|entry| entry.relative_path.to_lowercase());
+
+    append_log("list_markdown_files", &format!("{} files", files.len()));
+    Ok(files)
+}
+
+#[tauri::command]
+pub fn search_workspace(
+    directory: String,
+    query: String,
+    limit: Option<u32>,
+) -> Result<Vec<SearchHit>, AppError> {
+    let folder_path = PathBuf::from(directory);
+    if !folder_path.exists() || !folder_path.is_dir() {
+        return Err(AppError::new(
+            AppErrorCode::FileNotFound,
+            "Folder does not exist",
+        ));
+    }
+
+    let tokens: Vec<String> = query
+        .split_whitespace()
+        .map(|value| value.to_ascii_lowercase())
+        .filter(|value| !value.is_empty())
+        .collect();
+
+    if tokens.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let max_results = limit.unwrap_or(200).max(1) as usize;
+
+    let mut files = Vec::new();
+    let mut visited_dirs = HashSet::new();
+    collect_workspace_text_files(&folder_path, &folder_path, &mut files, &mut visited_dirs)?;
+
+    let mut hits = Vec::new();
+    for entry in files {
+        if hits.len() >= max_results {
+            break;
+        }
+
+        let path = PathBuf::from(&entry.path);
+        let content = match read_utf8(&path) {
+            Ok(content) => content,
+            Err(_) => continue,
+        };
+
+        let lower_content = content.to_ascii_lowercase();
+        if !tokens.iter().all(|token| lower_content.contains(token)) {
+            continue;
+        }
+
+        let first_index = lower_content.find(&tokens[0]).unwrap_or(0);
+        let line = (lower_content[..first_index]
+            .bytes()
+            .filter(|byte| *byte == b'\n')
+            .count()
+            + 1) as u32;
+
+        let snippet = build_snippet(&content, first_index);
+        hits.push(SearchHit {
+            path: entry.path,
+            name: entry.name,
+            relative_path: entry.relative_path,
+            line,
+            snippet,
+        });
+    }
+
+    append_log("search_workspace", &format!("query={query}; hits={}", hits.len()));
+    Ok(hits)
+}
+
+#[tauri::command]
+pub fn save_image_asset(
+    document_path: String,
+    file_name: String,
+    mime_type: String,
+    base64_data: String,
+) -> Result<SavedImageAsset, AppError> {
+    let document_path = PathBuf::from(document_path);
+    if !document_path.exists() || !is_text_openable_file(&document_path) {
+        return Err(AppError::new(
+            AppErrorCode::FileNotFound,
+            "Document path does not exist",
+        ));
+    }
+
+    let clean_base64 = base64_data
+        .split(",")
+        .last()
+        .unwrap_or(base64_data.as_str())
+        .trim()
+        .to_string();
+    let bytes = BASE64_STANDARD
+        .decode(clean_base64.as_bytes())
+        .map_err(|error| AppError::new(AppErrorCode::Io, error.to_string()))?;
+
+    let source_name = if file_name.trim().is_empty() {
+        "image".to_string()
+    } else {
+        file_name
+    };
+
+    let preferred_name = Path::new(&source_name)
+        .file_stem()
+        .and_then(|value| value.to_str())
+        .unwrap_or("image");
+
+    let saved = store_image_asset(&document_path, preferred_name, &bytes)?;
+    append_log("save_image_asset", &format!("{} (declared {})", saved.path, mime_type));
+    Ok(saved)
+}
+
+#[tauri::command]
+pub fn import_image_asset(document_path: String, source_path: String) -> Result<SavedImageAsset, AppError> {
+    let document_path = PathBuf::from(document_path);
+    let source_path = PathBuf::from(source_path);
+
+    if !document_path.exists() {
+        return Err(AppError::new(
+            AppErrorCode::FileNotFound,
+            "Document path does not exist",
+        ));
+    }
+
+    if !source_path.exists() || !source_path.is_file() || !is_image_file(&source_path) {
+        return Err(AppError::new(
+            AppErrorCode::FileNotFound,
+            "Image source does not exist",
+        ));
+    }
+
+    let bytes = fs::read(&source_path).map_err(|error| map_io_error(&error))?;
+    let preferred_name = source_path
+        .file_stem()
+        .and_then(|value| value.to_str())
+        .unwrap_or("image");
+
+    let saved = store_image_asset(&document_path, preferred_name, &bytes)?;
+    append_log("import_image_asset", &saved.path);
+    Ok(saved)
+}
+
+#[tauri::command]
+pub fn save_attachment(
+    document_path: String,
+    file_name: String,
+    base64_data: String,
+) -> Result<SavedAttachment, AppError> {
+    let document_path = PathBuf::from(document_path);
+    if !document_path.exists() {
+        return Err(AppError::new(
+            AppErrorCode::FileNotFound,
+            "Document path does not exist",
         ));
     }
 
-    let content = read_utf8(&file_path)?;
-    let mtime_ms = modified_ms(&file_path)?;
-    append_log("open_document", &file_path.to_string_lossy());
+    let clean_base64 = base64_data
+        .split(',')
+        .next_back()
+        .unwrap_or(base64_data.as_str())
+        .trim()
+        .to_string();
+    let bytes = BASE64_STANDARD
+        .decode(clean_base64.as_bytes())
+        .map_err(|error| AppError::new(AppErrorCode::Io, error.to_string()))?;
 
-    Ok(OpenDocumentResult {
-        path: file_path.to_string_lossy().to_string(),
-        content,
-        mtime_ms,
-    })
+    let (workspace_root, settings) = document_workspace_settings(&document_path)?;
+    let saved = save_attachment_bytes(&document_path, &file_name, &bytes, &settings, workspace_root.as_deref())?;
+    append_log("save_attachment", &saved.path);
+    Ok(saved)
 }
 
 #[tauri::command]
-pub fn save_document(
-    path: String,
-    content: String,
-    expected_mtime_ms: Option<u64>,
-) -> Result<SaveResult, AppError> {
-    let file_path = PathBuf::from(path);
-    if !file_path.exists() {
-        append_log("save_document_failed", "file not found");
+pub fn import_attachment(document_path: String, source_path: String) -> Result<SavedAttachment, AppError> {
+    let document_path = PathBuf::from(document_path);
+    let source_path = PathBuf::from(source_path);
+
+    if !document_path.exists() {
         return Err(AppError::new(
             AppErrorCode::FileNotFound,
-            "Cannot save because file no longer exists",
+            "Document path does not exist",
         ));
     }
-
-    if let Some(expected) = expected_mtime_ms {
-        let current = modified_ms(&file_path)?;
-        if current != expected {
-            append_log("save_document_failed", "mtime conflict");
-            return Err(AppError::new(
-                AppErrorCode::Conflict,
-                "File changed on disk. Reopen or Save As to avoid overwriting.",
-            ));
-        }
+    if !source_path.is_file() {
+        return Err(AppError::new(
+            AppErrorCode::FileNotFound,
+            "Attachment source does not exist",
+        ));
     }
 
-    atomic_write(&file_path, &content)?;
-    let mtime_ms = modified_ms(&file_path)?;
-    append_log("save_document", &file_path.to_string_lossy());
+    let (workspace_root, settings) = document_workspace_settings(&document_path)?;
+    let size_bytes = fs::metadata(&source_path).map_err(|error| map_io_error(&error))?.len();
+    if size_bytes > settings.attachments.max_size_bytes {
+        return Err(AppError::new(
+            AppErrorCode::PermissionDenied,
+            "Attachment is larger than the configured limit",
+        ));
+    }
 
-    Ok(SaveResult {
-        path: file_path.to_string_lossy().to_string(),
-        mtime_ms,
-        saved_at_ms: now_ms()?,
-    })
+    let bytes = fs::read(&source_path).map_err(|error| map_io_error(&error))?;
+    let file_name = source_path
+        .file_name()
+        .and_then(|value| value.to_str())
+        .unwrap_or("attachment");
+    let saved = save_attachment_bytes(&document_path, file_name, &bytes, &settings, workspace_root.as_deref())?;
+    append_log("import_attachment", &saved.path);
+    Ok(saved)
 }
 
 #[tauri::command]
-pub fn save_as_document(path: String, content: String) -> Result<SaveResult, AppError> {
-    let file_path = PathBuf::from(path);
-    atomic_write(&file_path, &content)?;
-    append_log("save_as_document", &file_path.to_string_lossy());
+pub fn create_snapshot(path: String, content: String, reason: String) -> Result<SnapshotEntry, AppError> {
+    if path.trim().is_empty() {
+        return Err(AppError::new(AppErrorCode::Io, "Snapshot path is empty"));
+    }
 
-    Ok(SaveResult {
-        path: file_path.to_string_lossy().to_string(),
-        mtime_ms: modified_ms(&file_path)?,
-        saved_at_ms: now_ms()?,
-    })
+    let store = load_settings_store(&settings_store_path()?)?;
+    let settings = workspace_settings_for(&store, Path::new(&path));
+    let entry = create_snapshot_in(
+        &history_dir()?,
+        &path,
+        &content,
+        &reason,
+        &settings.history,
+        &store.history_storage,
+    )?;
+
+    append_log("create_snapshot", &format!("{} ({})", path, reason));
+    Ok(entry)
 }
 
 #[tauri::command]
-pub fn write_text_file(path: String, content: String) -> Result<SaveResult, AppError> {
-    let file_path = PathBuf::from(path);
-    atomic_write(&file_path, &content)?;
-    append_log("write_text_file", &file_path.to_string_lossy());
+pub fn list_snapshots(path: String) -> Result<Vec<SnapshotEntry>, AppError> {
+    let history_root = history_dir()?;
+    let _lock = lock_history(&history_root)?;
+    let (index, key) = load_document_history(&history_root, &path)?;
+    let records = index.files.get(&key).cloned().unwrap_or_default();
 
-    Ok(SaveResult {
-        path: file_path.to_string_lossy().to_string(),
-        mtime_ms: modified_ms(&file_path)?,
-        saved_at_ms: now_ms()?,
-    })
-}
+    let mut entries: Vec<SnapshotEntry> = records.iter().map(snapshot_entry).collect();
 
-#[tauri::command]
-pub fn load_recovery_draft() -> Result<Option<String>, AppError> {
-    let path = recovery_draft_path()?;
-    append_log("load_recovery_draft", &path.to_string_lossy());
-    load_recovery_draft_from_path(&path)
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.created_at_ms));
+    Ok(entries)
 }
 
 #[tauri::command]
-pub fn store_recovery_draft(content: String) -> Result<(), AppError> {
-    let path = recovery_draft_path()?;
-    store_recovery_draft_at_path(&path, &content)?;
-    append_log(
-        "store_recovery_draft",
-        if content.trim().is_empty() { "clear" } else { "write" },
-    );
-    Ok(())
+pub fn set_snapshot_pinned(path: String, snapshot_id: String, pinned: bool) -> Result<SnapshotEntry, AppError> {
+    let history_root = history_dir()?;
+    let _lock = lock_history(&history_root)?;
+    let (mut index, key) = load_document_history(&history_root, &path)?;
+    let record = snapshot_record_mut(&mut index, &key, &snapshot_id)?;
+
+    record.pinned = pinned;
+    let entry = snapshot_entry(record);
+    save_history_index(&history_root, &mut index)?;
+    append_log("set_snapshot_pinned", &format!("{} {} ({})", path, snapshot_id, pinned));
+    Ok(entry)
 }
 
 #[tauri::command]
-pub fn list_markdown_files(directory: String) -> Result<Vec<MarkdownFileEntry>, AppError> {
-    let folder_path = PathBuf::from(directory);
-    if !folder_path.exists() || !folder_path.is_dir() {
-        append_log("list_markdown_files_failed", "directory missing");
-        return Err(AppError::new(
-            AppErrorCode::FileNotFound,
-            "Folder does not exist",
-        ));
+pub fn get_activity_timeline(query: ActivityQuery) -> Result<ActivityTimeline, AppError> {
+    let history_root = history_dir()?;
+    let _lock = lock_history(&history_root)?;
+    let mut index = load_history_index(&history_root)?;
+    if backfill_word_counts(&history_root, &mut index) {
+        save_history_index(&history_root, &mut index)?;
     }
 
-    let mut files = Vec::new();
-    let mut visited_dirs = HashSet::new();
-    collect_workspace_text_files(&folder_path, &folder_path, &mut files, &mut visited_dirs)?;
-    files.sort_by_key(|entry| entry.relative_path.to_lowercase());
-
-    append_log("list_markdown_files", &format!("{} files", files.len()));
-    Ok(files)
+    Ok(activity_timeline(&index, &query))
 }
 
 #[tauri::command]
-pub fn search_workspace(
-    directory: String,
+pub fn search_snapshots(
     query: String,
+    path: Option<String>,
     limit: Option<u32>,
-) -> Result<Vec<SearchHit>, AppError> {
-    let folder_path = PathBuf::from(directory);
-    if !folder_path.exists() || !folder_path.is_dir() {
-        return Err(AppError::new(
-            AppErrorCode::FileNotFound,
-            "Folder does not exist",
-        ));
-    }
-
+) -> Result<Vec<SnapshotSearchHit>, AppError> {
     let tokens: Vec<String> = query
         .split_whitespace()
         .map(|value| value.to_ascii_lowercase())
@@ -869,333 +5256,949 @@ pub fn search_workspace(
         return Ok(Vec::new());
     }
 
-    let max_results = limit.unwrap_or(200).max(1) as usize;
+    let max_results = limit.unwrap_or(200).max(1) as usize;
+    let history_root = history_dir()?;
+    let _lock = lock_history(&history_root)?;
+    let (index, document) = match &path {
+        Some(path) => {
+            let (index, key) = load_document_history(&history_root, path)?;
+            (index, Some(key))
+        }
+        None => (load_history_index(&history_root)?, None),
+    };
+
+    let hits = search_snapshots_in(&history_root, &index, &tokens, document.as_deref(), max_results);
+    append_log("search_snapshots", &format!("query={query}; hits={}", hits.len()));
+    Ok(hits)
+}
+
+#[tauri::command]
+pub fn export_document_history(path: String, archive_path: String) -> Result<usize, AppError> {
+    let count = export_history_archive(&history_dir()?, &path, Path::new(&archive_path))?;
+    append_log("export_document_history", &format!("{} -> {} ({} snapshots)", path, archive_path, count));
+    Ok(count)
+}
+
+#[tauri::command]
+pub fn import_document_history(archive_path: String, path: String) -> Result<Vec<SnapshotEntry>, AppError> {
+    let store = load_settings_store(&settings_store_path()?)?;
+    let entries = import_history_archive(&history_dir()?, Path::new(&archive_path), &path, &store.history_storage)?;
+    append_log("import_document_history", &format!("{} -> {}", archive_path, path));
+    Ok(entries)
+}
+
+#[tauri::command]
+pub fn find_history_candidates(path: String) -> Result<Vec<HistoryCandidate>, AppError> {
+    history_candidates_in(&history_dir()?, &path)
+}
+
+/// Attaches the history recorded for `old_path` to `new_path`, for files moved outside the app.
+#[tauri::command]
+pub fn reassociate_history(old_path: String, new_path: String) -> Result<Vec<SnapshotEntry>, AppError> {
+    let entries = move_document_history(&history_dir()?, &old_path, &new_path)?;
+    append_log("reassociate_history", &format!("{} -> {}", old_path, new_path));
+    Ok(entries)
+}
+
+#[tauri::command]
+pub fn repair_history() -> Result<HistoryRepairReport, AppError> {
+    let history_root = history_dir()?;
+    let _lock = lock_history(&history_root)?;
+    let mut index = load_history_index(&history_root)?;
+    let report = clean_history_orphans(&history_root, &mut index);
+    save_history_index(&history_root, &mut index)?;
+    append_log("repair_history", &format!("{:?}", report));
+    Ok(report)
+}
+
+#[tauri::command]
+pub fn update_snapshot_label(
+    path: String,
+    snapshot_id: String,
+    name: Option<String>,
+    notes: Option<String>,
+) -> Result<SnapshotEntry, AppError> {
+    let history_root = history_dir()?;
+    let _lock = lock_history(&history_root)?;
+    let (mut index, key) = load_document_history(&history_root, &path)?;
+    let record = snapshot_record_mut(&mut index, &key, &snapshot_id)?;
+
+    record.name = non_empty_label(name);
+    record.notes = non_empty_label(notes);
+    let entry = snapshot_entry(record);
+    save_history_index(&history_root, &mut index)?;
+    append_log("update_snapshot_label", &format!("{} {}", path, snapshot_id));
+    Ok(entry)
+}
+
+#[tauri::command]
+pub fn delete_snapshot(path: String, snapshot_id: String) -> Result<(), AppError> {
+    delete_snapshot_in(&history_dir()?, &path, &snapshot_id)?;
+    append_log("delete_snapshot", &format!("{} {}", path, snapshot_id));
+    Ok(())
+}
+
+#[tauri::command]
+pub fn load_workspace_settings(workspace_folder: String) -> Result<WorkspaceSettings, AppError> {
+    let store = load_settings_store(&settings_store_path()?)?;
+    Ok(store.workspaces.get(&workspace_folder).cloned().unwrap_or_default())
+}
+
+#[tauri::command]
+pub fn save_workspace_settings(workspace_folder: String, settings: WorkspaceSettings) -> Result<(), AppError> {
+    let path = settings_store_path()?;
+    let mut store = load_settings_store(&path)?;
+    store.workspaces.insert(workspace_folder.clone(), settings);
+    save_settings_store(&path, &store)?;
+    append_log("save_workspace_settings", &workspace_folder);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn load_history_storage_settings() -> Result<HistoryStorageSettings, AppError> {
+    Ok(load_settings_store(&settings_store_path()?)?.history_storage)
+}
+
+#[tauri::command]
+pub fn save_history_storage_settings(settings: HistoryStorageSettings) -> Result<(), AppError> {
+    let path = settings_store_path()?;
+    let mut store = load_settings_store(&path)?;
+    store.history_storage = settings.clone();
+    save_settings_store(&path, &store)?;
+
+    if let Some(quota_bytes) = settings.quota_bytes {
+        let history_root = history_dir()?;
+        let _lock = lock_history(&history_root)?;
+        let mut index = load_history_index(&history_root)?;
+        enforce_history_quota(&history_root, &mut index, quota_bytes)?;
+        save_history_index(&history_root, &mut index)?;
+    }
+
+    append_log("save_history_storage_settings", &format!("{:?}", settings.quota_bytes));
+    Ok(())
+}
+
+fn diff_kind(tag: ChangeTag) -> &'static str {
+    match tag {
+        ChangeTag::Equal => "equal",
+        ChangeTag::Insert => "insert",
+        ChangeTag::Delete => "delete",
+    }
+}
+
+fn diff_texts(old: &str, new: &str) -> SnapshotDiff {
+    let diff = TextDiff::from_lines(old, new);
+    let mut hunks = Vec::new();
+    let mut stats = DiffStats::default();
+
+    for group in diff.grouped_ops(3) {
+        let (Some(first), Some(last)) = (group.first(), group.last()) else {
+            continue;
+        };
+        let old_range = first.old_range().start..last.old_range().end;
+        let new_range = first.new_range().start..last.new_range().end;
+        let mut lines = Vec::new();
+
+        for op in &group {
+            for change in diff.iter_inline_changes(op) {
+                let tag = change.tag();
+                let mut text = String::new();
+                let mut segments = Vec::new();
+                let parts: Vec<(bool, _)> = change.iter_strings_lossy().collect();
+                let whole_line = tag != ChangeTag::Equal && parts.iter().all(|(emphasized, _)| !emphasized);
+                for (emphasized, value) in parts {
+                    text.push_str(&value);
+                    let highlighted = emphasized || whole_line;
+                    let kind = if highlighted { diff_kind(tag) } else { "equal" };
+                    if highlighted {
+                        let words = value.split_whitespace().count();
+                        match tag {
+                            ChangeTag::Insert => stats.words_added += words,
+                            ChangeTag::Delete => stats.words_removed += words,
+                            ChangeTag::Equal => {}
+                        }
+                    }
+                    match segments.last_mut() {
+                        Some(DiffSegment { kind: last_kind, text: last_text }) if last_kind == kind => {
+                            last_text.push_str(&value);
+                        }
+                        _ => segments.push(DiffSegment {
+                            kind: kind.to_string(),
+                            text: value.to_string(),
+                        }),
+                    }
+                }
+                match tag {
+                    ChangeTag::Insert => stats.lines_added += 1,
+                    ChangeTag::Delete => stats.lines_removed += 1,
+                    ChangeTag::Equal => {}
+                }
+                if text.ends_with('\n') {
+                    text.pop();
+                    if let Some(segment) = segments.last_mut() {
+                        segment.text.pop();
+                    }
+                }
+                segments.retain(|segment| !segment.text.is_empty());
+                lines.push(DiffLine {
+                    kind: diff_kind(tag).to_string(),
+                    old_line: change.old_index().map(|index| index + 1),
+                    new_line: change.new_index().map(|index| index + 1),
+                    text,
+                    segments,
+                });
+            }
+        }
+
+        hunks.push(DiffHunk {
+            old_start: old_range.start + 1,
+            old_lines: old_range.len(),
+            new_start: new_range.start + 1,
+            new_lines: new_range.len(),
+            lines,
+        });
+    }
+
+    SnapshotDiff { hunks, stats }
+}
+
+fn snapshot_content(
+    history_root: &Path,
+    index: &HistoryIndex,
+    path: &str,
+    snapshot_id: &str,
+) -> Result<String, AppError> {
+    let record = index
+        .files
+        .get(path)
+        .and_then(|records| records.iter().find(|record| record.id == snapshot_id))
+        .ok_or_else(|| AppError::new(AppErrorCode::FileNotFound, "Snapshot not found"))?;
+    load_record_content(history_root, index, record)
+}
+
+/// Diffs a snapshot against a newer snapshot, or against the given buffer when no second id is passed.
+#[tauri::command]
+pub fn diff_snapshots(
+    path: String,
+    from_snapshot_id: String,
+    to_snapshot_id: Option<String>,
+    content: Option<String>,
+) -> Result<SnapshotDiff, AppError> {
+    let history_root = history_dir()?;
+    let _lock = lock_history(&history_root)?;
+    let (index, key) = load_document_history(&history_root, &path)?;
+    let old = snapshot_content(&history_root, &index, &key, &from_snapshot_id)?;
+    let new = match (to_snapshot_id, content) {
+        (Some(snapshot_id), _) => snapshot_content(&history_root, &index, &key, &snapshot_id)?,
+        (None, Some(content)) => content,
+        (None, None) => {
+            return Err(AppError::new(
+                AppErrorCode::Conflict,
+                "Nothing to compare the snapshot against",
+            ))
+        }
+    };
+
+    Ok(diff_texts(&old, &new))
+}
+
+#[tauri::command]
+pub fn load_snapshot(path: String, snapshot_id: String) -> Result<OpenDocumentResult, AppError> {
+    let history_root = history_dir()?;
+    let _lock = lock_history(&history_root)?;
+    let (index, key) = load_document_history(&history_root, &path)?;
+    let records = index.files.get(&key).ok_or_else(|| {
+        AppError::new(AppErrorCode::FileNotFound, "No snapshots available for this document")
+    })?;
+
+    let record = records
+        .iter()
+        .find(|record| record.id == snapshot_id)
+        .ok_or_else(|| AppError::new(AppErrorCode::FileNotFound, "Snapshot not found"))?;
+
+    let content = load_record_content(&history_root, &index, record)?;
+    let mtime_ms = if Path::new(&path).exists() {
+        modified_ms(Path::new(&path))?
+    } else {
+        record.created_at_ms
+    };
+
+    Ok(OpenDocumentResult {
+        path,
+        content,
+        mtime_ms,
+    })
+}
+
+#[tauri::command]
+pub fn validate_links(
+    document_path: String,
+    markdown: String,
+    check_external: bool,
+    external_options: Option<ExternalCheckOptions>,
+    workspace_folder: Option<String>,
+) -> Result<LinkValidationReport, AppError> {
+    let cache_path = external_link_cache_path()?;
+    validate_links_with_cache(
+        Path::new(&document_path),
+        &markdown,
+        workspace_folder.as_deref().map(Path::new),
+        check_external,
+        &external_options.unwrap_or_default(),
+        Some(&cache_path),
+    )
+}
+
+fn validate_links_with_cache(
+    document_path: &Path,
+    markdown: &str,
+    workspace_root: Option<&Path>,
+    check_external: bool,
+    external_options: &ExternalCheckOptions,
+    cache_path: Option<&Path>,
+) -> Result<LinkValidationReport, AppError> {
+    let document_path = document_path.to_path_buf();
+    let document_dir = document_path
+        .parent()
+        .ok_or_else(|| AppError::new(AppErrorCode::Io, "Document path has no parent"))?
+        .to_path_buf();
+    let workspace_root = workspace_root.unwrap_or(&document_dir).to_path_buf();
+
+    let current_anchor_slugs = heading_slugs(markdown);
+    let extracted = extract_markdown_links(markdown);
+    let mut issues = Vec::new();
+    let mut workspace_files: Option<Vec<PathBuf>> = None;
+
+    let external_statuses = if check_external {
+        let urls: Vec<String> = extracted
+            .links
+            .iter()
+            .filter(|entry| is_external_link(&entry.target))
+            .map(|entry| entry.target.clone())
+            .collect();
+        check_external_urls(&urls, external_options, cache_path)?
+    } else {
+        HashMap::new()
+    };
 
-    let mut files = Vec::new();
-    let mut visited_dirs = HashSet::new();
-    collect_workspace_text_files(&folder_path, &folder_path, &mut files, &mut visited_dirs)?;
+    for entry in &extracted.links {
+        let line = entry.line;
+        let link = entry.target.as_str();
+        if link.trim().is_empty() || is_ignored_link(link) {
+            continue;
+        }
 
-    let mut hits = Vec::new();
-    for entry in files {
-        if hits.len() >= max_results {
-            break;
+        if is_external_link(link) {
+            if let Some(mut issue) = external_statuses
+                .get(link)
+                .and_then(|status| external_status_issue(line, &entry.display, status))
+            {
+                issue.column = Some(entry.column);
+                issues.push(issue);
+            }
+            continue;
         }
 
-        let path = PathBuf::from(&entry.path);
-        let content = match read_utf8(&path) {
-            Ok(content) => content,
-            Err(_) => continue,
+        let (path_part, anchor_part) = split_link_and_anchor(link);
+        let target_path = resolve_link_path(&document_path, &workspace_root, &path_part);
+        let case_match = if path_part.is_empty() {
+            None
+        } else {
+            case_mismatch_path(&target_path)
         };
 
-        let lower_content = content.to_ascii_lowercase();
-        if !tokens.iter().all(|token| lower_content.contains(token)) {
+        if !target_path.exists() || case_match.is_some() {
+            let mut issue = if target_path.exists() {
+                LinkValidationIssue::new(
+                    line,
+                    entry.display.clone(),
+                    "warning",
+                    "Link target differs from the file name only by letter case",
+                )
+            } else {
+                LinkValidationIssue::new(line, entry.display.clone(), "error", "Target file does not exist")
+            };
+            issue.column = Some(entry.column);
+
+            let (kind, suggested_path) = match case_match {
+                Some(actual) => ("case", Some(actual)),
+                None => {
+                    let candidates = workspace_files.get_or_insert_with(|| {
+                        let mut files = Vec::new();
+                        let mut visited_dirs = HashSet::new();
+                        let _ = collect_workspace_paths(&workspace_root, &mut files, &mut visited_dirs);
+                        files
+                    });
+                    ("file", closest_workspace_file(&target_path, candidates))
+                }
+            };
+
+            if let Some(suggested_path) = suggested_path {
+                let relative = if path_part.starts_with('/') {
+                    format!("/{}", relative_link_path(&workspace_root, &suggested_path))
+                } else {
+                    relative_link_path(&document_dir, &suggested_path)
+                };
+                let replacement = rebuild_link_target(entry, &path_part, &relative, anchor_part.as_deref());
+                issue.suggestions.push(LinkFixSuggestion {
+                    kind: kind.to_string(),
+                    description: format!("Link to {relative}"),
+                    replacement,
+                });
+            }
+
+            issues.push(issue);
             continue;
         }
 
-        let first_index = lower_content.find(&tokens[0]).unwrap_or(0);
-        let line = (lower_content[..first_index]
-            .bytes()
-            .filter(|byte| *byte == b'\n')
-            .count()
-            + 1) as u32;
+        if matches!(entry.kind, LinkKind::Image | LinkKind::HtmlImage) {
+            let size_bytes = fs::metadata(&target_path).map(|metadata| metadata.len()).unwrap_or(0);
+            if size_bytes > LARGE_IMAGE_BYTES {
+                let mut issue = LinkValidationIssue::new(
+                    line,
+                    entry.display.clone(),
+                    "warning",
+                    format!("Image is large ({:.1} MB)", size_bytes as f64 / (1024.0 * 1024.0)),
+                );
+                issue.column = Some(entry.column);
+                issues.push(issue);
+            }
+        }
 
-        let snippet = build_snippet(&content, first_index);
-        hits.push(SearchHit {
-            path: entry.path,
-            name: entry.name,
-            relative_path: entry.relative_path,
-            line,
-            snippet,
-        });
+        if let Some(anchor) = anchor_part {
+            if anchor.trim().is_empty() {
+                continue;
+            }
+
+            let anchor = anchor.to_ascii_lowercase();
+            let slug_set = if target_path == document_path {
+                current_anchor_slugs.clone()
+            } else if is_text_openable_file(&target_path) {
+                match read_utf8(&target_path) {
+                    Ok(content) => heading_slugs(&content),
+                    Err(_) => HashSet::new(),
+                }
+            } else {
+                HashSet::new()
+            };
+
+            if !slug_set.contains(&anchor) {
+                let mut issue = LinkValidationIssue::new(
+                    line,
+                    entry.display.clone(),
+                    "error",
+                    "Anchor was not found in target document",
+                );
+                issue.column = Some(entry.column);
+                if let Some(slug) = closest_heading_slug(&anchor, &slug_set) {
+                    issue.suggestions.push(LinkFixSuggestion {
+                        kind: "anchor".to_string(),
+                        description: format!("Link to heading #{slug}"),
+                        replacement: rebuild_link_target(entry, &path_part, &path_part, Some(&slug)),
+                    });
+                }
+                issues.push(issue);
+            }
+        }
     }
 
-    append_log("search_workspace", &format!("query={query}; hits={}", hits.len()));
-    Ok(hits)
-}
+    let defined_labels: HashSet<&str> = extracted
+        .definitions
+        .iter()
+        .map(|definition| definition.label.as_str())
+        .collect();
+    let mut used_labels: HashSet<&str> = HashSet::new();
 
-#[tauri::command]
-pub fn save_image_asset(
-    document_path: String,
-    file_name: String,
-    mime_type: String,
-    base64_data: String,
-) -> Result<SavedImageAsset, AppError> {
-    let document_path = PathBuf::from(document_path);
-    if !document_path.exists() || !is_text_openable_file(&document_path) {
-        return Err(AppError::new(
-            AppErrorCode::FileNotFound,
-            "Document path does not exist",
-        ));
+    for usage in &extracted.references {
+        if defined_labels.contains(usage.label.as_str()) {
+            used_labels.insert(usage.label.as_str());
+        } else {
+            issues.push(LinkValidationIssue::new(
+                usage.line,
+                usage.display.clone(),
+                "error",
+                "Reference is not defined",
+            ));
+        }
     }
 
-    let clean_base64 = base64_data
-        .split(",")
-        .last()
-        .unwrap_or(base64_data.as_str())
-        .trim()
-        .to_string();
-    let bytes = BASE64_STANDARD
-        .decode(clean_base64.as_bytes())
-        .map_err(|error| AppError::new(AppErrorCode::Io, error.to_string()))?;
+    for usage in &extracted.shortcuts {
+        if defined_labels.contains(usage.label.as_str()) {
+            used_labels.insert(usage.label.as_str());
+        }
+    }
 
-    let source_name = if file_name.trim().is_empty() {
-        "image".to_string()
-    } else {
-        file_name
-    };
+    for definition in &extracted.definitions {
+        if !used_labels.contains(definition.label.as_str()) {
+            issues.push(LinkValidationIssue::new(
+                definition.line,
+                format!("[{}]: {}", definition.label, definition.target),
+                "warning",
+                "Reference definition is never used",
+            ));
+        }
+    }
 
-    let extension = ext_from_mime(&mime_type)
-        .map(|value| value.to_string())
-        .or_else(|| ext_from_path(Path::new(&source_name)))
-        .unwrap_or_else(|| "png".to_string());
+    issues.sort_by_key(|issue| issue.line);
 
-    let preferred_name = Path::new(&source_name)
-        .file_stem()
-        .and_then(|value| value.to_str())
-        .unwrap_or("image");
+    Ok(LinkValidationReport {
+        checked_external: check_external,
+        issues,
+    })
+}
 
-    let saved = save_asset_bytes(&document_path, preferred_name, &extension, &bytes)?;
-    append_log("save_image_asset", &saved.path);
-    Ok(saved)
+fn replace_link_at_column(line: &str, column: usize, link: &str, replacement: &str) -> Option<String> {
+    let rest = line.get(column..)?;
+    let expected = if link.starts_with("[[") { link.trim_end_matches("]]") } else { link };
+    if !rest.starts_with(expected) {
+        return None;
+    }
+    let updated = replace_link_in_line(rest, link, replacement)?;
+    Some(format!("{}{}", &line[..column], updated))
 }
 
 #[tauri::command]
-pub fn import_image_asset(document_path: String, source_path: String) -> Result<SavedImageAsset, AppError> {
-    let document_path = PathBuf::from(document_path);
-    let source_path = PathBuf::from(source_path);
+pub fn apply_link_fix(
+    markdown: String,
+    line: u32,
+    column: usize,
+    link: String,
+    replacement: String,
+) -> Result<String, AppError> {
+    let mut output = String::with_capacity(markdown.len() + replacement.len());
+    let mut applied = false;
+
+    for (index, source_line) in markdown.split_inclusive('\n').enumerate() {
+        if applied || index + 1 != line as usize {
+            output.push_str(source_line);
+            continue;
+        }
 
-    if !document_path.exists() {
-        return Err(AppError::new(
-            AppErrorCode::FileNotFound,
-            "Document path does not exist",
-        ));
+        match replace_link_at_column(source_line, column, &link, &replacement) {
+            Some(updated) => {
+                output.push_str(&updated);
+                applied = true;
+            }
+            None => output.push_str(source_line),
+        }
     }
 
-    if !source_path.exists() || !source_path.is_file() || !is_image_file(&source_path) {
+    if !applied {
         return Err(AppError::new(
-            AppErrorCode::FileNotFound,
-            "Image source does not exist",
+            AppErrorCode::Conflict,
+            "Link was not found at the given position",
         ));
     }
 
-    let bytes = fs::read(&source_path).map_err(|error| map_io_error(&error))?;
-    let extension = ext_from_path(&source_path).unwrap_or_else(|| "png".to_string());
-    let preferred_name = source_path
-        .file_stem()
-        .and_then(|value| value.to_str())
-        .unwrap_or("image");
-
-    let saved = save_asset_bytes(&document_path, preferred_name, &extension, &bytes)?;
-    append_log("import_image_asset", &saved.path);
-    Ok(saved)
+    append_log("apply_link_fix", &format!("line {line}: {link} -> {replacement}"));
+    Ok(output)
 }
 
 #[tauri::command]
-pub fn create_snapshot(path: String, content: String, reason: String) -> Result<SnapshotEntry, AppError> {
-    if path.trim().is_empty() {
-        return Err(AppError::new(AppErrorCode::Io, "Snapshot path is empty"));
-    }
+pub fn get_backlinks(workspace_folder: String, document_path: String) -> Result<Vec<BacklinkEntry>, AppError> {
+    let workspace_root = require_workspace_folder(&workspace_folder)?;
+    let document_key = normalize_lexical_path(Path::new(&document_path));
 
-    let now = now_ms()?;
-    let mut index = load_history_index()?;
-    let records = index.files.entry(path.clone()).or_default();
-
-    let content_hash = hash_u64(&content);
-    if let Some(last) = records.last() {
-        if last.content_hash == content_hash {
-            return Ok(SnapshotEntry {
-                id: last.id.clone(),
-                created_at_ms: last.created_at_ms,
-                reason: last.reason.clone(),
-                size_bytes: last.size_bytes,
-            });
+    let mut backlinks = Vec::new();
+    for document in index_workspace_links(&workspace_root)? {
+        if normalize_lexical_path(Path::new(&document.entry.path)) == document_key {
+            continue;
         }
 
-        if reason == "autosave" && last.reason == "autosave" {
-            if now.saturating_sub(last.created_at_ms) < 60_000 {
-                return Ok(SnapshotEntry {
-                    id: last.id.clone(),
-                    created_at_ms: last.created_at_ms,
-                    reason: last.reason.clone(),
-                    size_bytes: last.size_bytes,
-                });
+        for resolved in &document.links {
+            if resolved.target_path.as_ref() != Some(&document_key) {
+                continue;
             }
+            backlinks.push(BacklinkEntry {
+                path: document.entry.path.clone(),
+                relative_path: document.entry.relative_path.clone(),
+                line: resolved.link.line,
+                link: resolved.link.display.clone(),
+                context: line_context(&document.content, resolved.link.line, &resolved.link.display),
+            });
         }
     }
 
-    let snapshot_id = format!("{}-{:x}", now, hash_u64(&format!("{}:{}", path, now)));
-    let snapshot_folder = snapshot_dir_for_document(&path)?;
-    fs::create_dir_all(&snapshot_folder).map_err(|error| map_io_error(&error))?;
+    append_log("get_backlinks", &format!("{} ({} links)", document_path, backlinks.len()));
+    Ok(backlinks)
+}
+
+#[tauri::command]
+pub fn get_outgoing_links(
+    workspace_folder: String,
+    document_path: String,
+    markdown: Option<String>,
+) -> Result<Vec<OutgoingLinkEntry>, AppError> {
+    let workspace_root = require_workspace_folder(&workspace_folder)?;
+    let path = PathBuf::from(&document_path);
+    let content = match markdown {
+        Some(markdown) => markdown,
+        None => read_utf8(&path)?,
+    };
 
-    let snapshot_file = snapshot_folder.join(format!("{}.mdsnap", snapshot_id));
-    atomic_write(&snapshot_file, &content)?;
+    let links = resolve_document_links(&path, &workspace_root, &content)
+        .into_iter()
+        .filter(|resolved| !is_ignored_link(&resolved.link.target))
+        .map(|resolved| OutgoingLinkEntry {
+            line: resolved.link.line,
+            exists: resolved.target_path.as_ref().is_some_and(|target| target.exists()),
+            target_path: resolved
+                .target_path
+                .map(|target| target.to_string_lossy().to_string()),
+            kind: resolved.link.kind.as_str().to_string(),
+            link: resolved.link.display,
+        })
+        .collect();
 
-    let size_bytes = content.as_bytes().len() as u64;
-    records.push(SnapshotRecord {
-        id: snapshot_id.clone(),
-        created_at_ms: now,
-        reason: reason.clone(),
-        size_bytes,
-        file_path: snapshot_file.to_string_lossy().to_string(),
-        content_hash,
-    });
+    Ok(links)
+}
 
-    if records.len() > 50 {
-        let overflow = records.len() - 50;
-        let to_remove: Vec<SnapshotRecord> = records.drain(0..overflow).collect();
-        for stale in to_remove {
-            let stale_path = PathBuf::from(stale.file_path);
-            let _ = fs::remove_file(stale_path);
+#[tauri::command]
+pub fn find_orphan_notes(workspace_folder: String) -> Result<Vec<MarkdownFileEntry>, AppError> {
+    let workspace_root = require_workspace_folder(&workspace_folder)?;
+    let documents = index_workspace_links(&workspace_root)?;
+
+    let mut linked: HashSet<PathBuf> = HashSet::new();
+    for document in &documents {
+        let source = normalize_lexical_path(Path::new(&document.entry.path));
+        for resolved in &document.links {
+            if let Some(target) = &resolved.target_path {
+                if *target != source {
+                    linked.insert(target.clone());
+                }
+            }
         }
     }
 
-    save_history_index(&index)?;
-    append_log("create_snapshot", &format!("{} ({})", path, reason));
+    let orphans: Vec<MarkdownFileEntry> = documents
+        .into_iter()
+        .filter(|document| !linked.contains(&normalize_lexical_path(Path::new(&document.entry.path))))
+        .map(|document| document.entry)
+        .collect();
 
-    Ok(SnapshotEntry {
-        id: snapshot_id,
-        created_at_ms: now,
-        reason,
-        size_bytes,
-    })
+    append_log("find_orphan_notes", &format!("{} orphans", orphans.len()));
+    Ok(orphans)
 }
 
+/// Dimensions, format, size and an optional cached thumbnail for each image the document embeds.
 #[tauri::command]
-pub fn list_snapshots(path: String) -> Result<Vec<SnapshotEntry>, AppError> {
-    let index = load_history_index()?;
-    let records = index.files.get(&path).cloned().unwrap_or_default();
+pub fn get_document_images(
+    document_path: String,
+    markdown: Option<String>,
+    workspace_folder: Option<String>,
+    thumbnail_size: Option<u32>,
+) -> Result<Vec<ImageInfo>, AppError> {
+    let document_path = PathBuf::from(document_path);
+    let markdown = match markdown {
+        Some(markdown) => markdown,
+        None => read_utf8(&document_path)?,
+    };
+    let cache_dir = thumbnail_cache_dir()?;
+    document_image_info(
+        &document_path,
+        &markdown,
+        workspace_folder.as_deref().map(Path::new),
+        thumbnail_size.or(Some(DEFAULT_THUMBNAIL_SIZE)).filter(|size| *size > 0),
+        Some(&cache_dir),
+    )
+}
+
+#[tauri::command]
+pub fn find_orphaned_assets(workspace_folder: String) -> Result<Vec<OrphanedAsset>, AppError> {
+    let workspace_root = require_workspace_folder(&workspace_folder)?;
+    let store = load_settings_store(&settings_store_path()?)?;
+    let settings = workspace_settings_for(&store, &workspace_root);
+    let orphans = find_orphaned_assets_in(&workspace_root, &settings.assets)?;
+    append_log("find_orphaned_assets", &format!("{} orphans", orphans.len()));
+    Ok(orphans)
+}
+
+/// Moves the given orphaned assets to the system trash in one batch.
+#[tauri::command]
+pub fn trash_orphaned_assets(workspace_folder: String, paths: Vec<String>) -> Result<usize, AppError> {
+    let workspace_root = require_workspace_folder(&workspace_folder)?;
+    let store = load_settings_store(&settings_store_path()?)?;
+    let settings = workspace_settings_for(&store, &workspace_root);
+    let targets = orphaned_assets_to_trash(&workspace_root, &settings.assets, &paths)?;
+    if targets.is_empty() {
+        return Ok(0);
+    }
+
+    trash::delete_all(&targets).map_err(|error| AppError::new(AppErrorCode::Io, error.to_string()))?;
+    append_log("trash_orphaned_assets", &format!("{} files", targets.len()));
+    Ok(targets.len())
+}
+
+#[tauri::command]
+pub fn export_link_graph(workspace_folder: String) -> Result<LinkGraph, AppError> {
+    let workspace_root = require_workspace_folder(&workspace_folder)?;
+    let documents = index_workspace_links(&workspace_root)?;
+
+    let ids: HashMap<PathBuf, String> = documents
+        .iter()
+        .map(|document| {
+            (
+                normalize_lexical_path(Path::new(&document.entry.path)),
+                document.entry.relative_path.clone(),
+            )
+        })
+        .collect();
+
+    let mut edge_counts: HashMap<(String, String), u32> = HashMap::new();
+    for document in &documents {
+        for resolved in &document.links {
+            let Some(target_id) = resolved.target_path.as_ref().and_then(|target| ids.get(target)) else {
+                continue;
+            };
+            if *target_id == document.entry.relative_path {
+                continue;
+            }
+            *edge_counts
+                .entry((document.entry.relative_path.clone(), target_id.clone()))
+                .or_default() += 1;
+        }
+    }
 
-    let mut entries: Vec<SnapshotEntry> = records
+    let mut edges: Vec<LinkGraphEdge> = edge_counts
         .into_iter()
-        .map(|record| SnapshotEntry {
-            id: record.id,
-            created_at_ms: record.created_at_ms,
-            reason: record.reason,
-            size_bytes: record.size_bytes,
+        .map(|((source, target), count)| LinkGraphEdge { source, target, count })
+        .collect();
+    edges.sort_by(|left, right| (&left.source, &left.target).cmp(&(&right.source, &right.target)));
+
+    let nodes = documents
+        .into_iter()
+        .map(|document| {
+            let id = document.entry.relative_path;
+            LinkGraphNode {
+                inbound: edges.iter().filter(|edge| edge.target == id).count() as u32,
+                outbound: edges.iter().filter(|edge| edge.source == id).count() as u32,
+                path: document.entry.path,
+                name: document.entry.name,
+                id,
+            }
         })
         .collect();
 
-    entries.sort_by(|left, right| right.created_at_ms.cmp(&left.created_at_ms));
-    Ok(entries)
+    append_log("export_link_graph", &format!("{} edges", edges.len()));
+    Ok(LinkGraph { nodes, edges })
 }
 
 #[tauri::command]
-pub fn load_snapshot(path: String, snapshot_id: String) -> Result<OpenDocumentResult, AppError> {
-    let index = load_history_index()?;
-    let records = index.files.get(&path).ok_or_else(|| {
-        AppError::new(AppErrorCode::FileNotFound, "No snapshots available for this document")
-    })?;
+pub fn preview_document_rename(
+    workspace_folder: String,
+    old_path: String,
+    new_path: String,
+) -> Result<DocumentRenamePlan, AppError> {
+    let workspace_root = require_workspace_folder(&workspace_folder)?;
+    let (_, rewrites) = plan_document_rename(&workspace_root, Path::new(&old_path), Path::new(&new_path))?;
+    Ok(rename_plan_dto(Path::new(&old_path), Path::new(&new_path), &rewrites))
+}
 
-    let record = records
-        .iter()
-        .find(|record| record.id == snapshot_id)
-        .ok_or_else(|| AppError::new(AppErrorCode::FileNotFound, "Snapshot not found"))?;
+fn snapshot_before_rewrite(
+    history_root: &Path,
+    store: &SettingsStore,
+    path: &str,
+    content: &str,
+    reason: &str,
+) -> Result<SnapshotEntry, AppError> {
+    let settings = workspace_settings_for(store, Path::new(path));
+    create_snapshot_in(history_root, path, content, reason, &settings.history, &store.history_storage)
+}
 
-    let snapshot_path = PathBuf::from(&record.file_path);
-    if !snapshot_path.exists() {
+fn rename_document_in(
+    history_root: &Path,
+    store: &SettingsStore,
+    workspace_root: &Path,
+    old_file: &Path,
+    new_file: &Path,
+) -> Result<Vec<PendingRewrite>, AppError> {
+    if !old_file.is_file() {
         return Err(AppError::new(
             AppErrorCode::FileNotFound,
-            "Snapshot file is missing on disk",
+            "Document does not exist",
+        ));
+    }
+    if new_file.exists() {
+        return Err(AppError::new(
+            AppErrorCode::Conflict,
+            "A file already exists at the new location",
         ));
     }
 
-    let content = read_utf8(&snapshot_path)?;
-    let mtime_ms = if Path::new(&path).exists() {
-        modified_ms(Path::new(&path))?
-    } else {
-        record.created_at_ms
+    let old_path = old_file.to_string_lossy().to_string();
+    let new_path = new_file.to_string_lossy().to_string();
+    let (documents, rewrites) = plan_document_rename(workspace_root, old_file, new_file)?;
+    let old_key = normalize_lexical_path(old_file);
+
+    let mut writes: Vec<(PathBuf, String)> = Vec::new();
+    let mut moved_content = None;
+    for document in &documents {
+        let source = PathBuf::from(&document.entry.path);
+        let is_moved = normalize_lexical_path(&source) == old_key;
+        let file_rewrites: Vec<&PendingRewrite> = rewrites
+            .iter()
+            .filter(|rewrite| rewrite.path == source)
+            .collect();
+        if file_rewrites.is_empty() && !is_moved {
+            continue;
+        }
+
+        snapshot_before_rewrite(history_root, store, &document.entry.path, &document.content, "rename")?;
+        let updated = apply_rewrites_to_content(&document.content, &file_rewrites);
+        if is_moved {
+            moved_content = Some(updated);
+        } else {
+            writes.push((source, updated));
+        }
+    }
+
+    let moved_content = match moved_content {
+        Some(content) => content,
+        None => {
+            let content = read_utf8(old_file)?;
+            snapshot_before_rewrite(history_root, store, &old_path, &content, "rename")?;
+            content
+        }
     };
+    writes.push((new_file.to_path_buf(), moved_content));
 
-    Ok(OpenDocumentResult {
-        path,
-        content,
-        mtime_ms,
-    })
-}
+    let originals = write_files_atomically(&writes)?;
+    if let Err(error) = move_document_history(history_root, &old_path, &new_path) {
+        restore_files(&originals);
+        return Err(error);
+    }
+    if let Err(error) = fs::remove_file(old_file) {
+        restore_files(&originals);
+        let _ = move_document_history(history_root, &new_path, &old_path);
+        return Err(map_io_error(&error));
+    }
 
-#[tauri::command]
-pub fn validate_links(
-    document_path: String,
-    markdown: String,
-    check_external: bool,
-) -> Result<LinkValidationReport, AppError> {
-    let document_path = PathBuf::from(document_path);
-    let document_dir = document_path
-        .parent()
-        .ok_or_else(|| AppError::new(AppErrorCode::Io, "Document path has no parent"))?
-        .to_path_buf();
+    Ok(rewrites)
+}
 
-    let current_anchor_slugs = heading_slugs(&markdown);
-    let mut issues = Vec::new();
+#[tauri::command]
+pub fn rename_document(
+    workspace_folder: String,
+    old_path: String,
+    new_path: String,
+) -> Result<DocumentRenamePlan, AppError> {
+    let workspace_root = require_workspace_folder(&workspace_folder)?;
+    let old_file = PathBuf::from(&old_path);
+    let new_file = PathBuf::from(&new_path);
+    let store = load_settings_store(&settings_store_path()?)?;
+    let rewrites = rename_document_in(&history_dir()?, &store, &workspace_root, &old_file, &new_file)?;
 
-    for (line, link) in extract_markdown_links(&markdown) {
-        if link.trim().is_empty() || is_ignored_link(&link) {
-            continue;
-        }
+    append_log(
+        "rename_document",
+        &format!("{} -> {} ({} links)", old_path, new_path, rewrites.len()),
+    );
+    Ok(rename_plan_dto(&old_file, &new_file, &rewrites))
+}
 
-        if is_external_link(&link) {
-            if check_external && !external_url_reachable(&link) {
-                issues.push(LinkValidationIssue {
-                    line,
-                    link: link.clone(),
-                    severity: "warning".to_string(),
-                    message: "External URL did not respond to a quick reachability check".to_string(),
-                });
-            }
-            continue;
-        }
+#[allow(clippy::too_many_arguments)]
+fn rename_heading_in(
+    history_root: &Path,
+    store: &SettingsStore,
+    workspace_root: &Path,
+    document_file: &Path,
+    old_heading: &str,
+    new_heading: &str,
+    markdown: Option<&str>,
+    apply: bool,
+) -> Result<HeadingRenamePlan, AppError> {
+    let document_path = document_file.to_string_lossy().to_string();
+    let old_slug = heading_text_slug(old_heading);
+    let new_slug = heading_text_slug(new_heading);
+    if old_slug.is_empty() || new_slug.is_empty() {
+        return Err(AppError::new(AppErrorCode::Io, "Heading text is empty"));
+    }
 
-        let (path_part, anchor_part) = split_link_and_anchor(&link);
-        let target_path = if path_part.is_empty() {
-            document_path.clone()
-        } else {
-            document_dir.join(&path_part)
-        };
+    // An editor buffer may already carry the new heading text; the file on disk must have the old one.
+    let document_content = match markdown {
+        Some(markdown) => markdown.to_string(),
+        None => read_utf8(document_file)?,
+    };
+    let has_heading = heading_slugs(&document_content).contains(&old_slug)
+        || (markdown.is_some() && heading_slugs(&document_content).contains(&new_slug));
+    if !has_heading {
+        return Err(AppError::new(AppErrorCode::FileNotFound, "Heading not found in document"));
+    }
 
-        if !target_path.exists() {
-            issues.push(LinkValidationIssue {
-                line,
-                link: link.clone(),
-                severity: "error".to_string(),
-                message: "Target file does not exist".to_string(),
-            });
+    let (documents, rewrites) = plan_heading_rename(workspace_root, document_file, markdown, &old_slug, new_heading)?;
+
+    let document_key = normalize_lexical_path(document_file);
+    let mut updated_markdown = None;
+    let mut writes: Vec<(PathBuf, String)> = Vec::new();
+    for document in &documents {
+        let source = PathBuf::from(&document.entry.path);
+        let file_rewrites: Vec<&PendingRewrite> = rewrites
+            .iter()
+            .filter(|rewrite| rewrite.path == source)
+            .collect();
+        if normalize_lexical_path(&source) == document_key || !apply || file_rewrites.is_empty() {
             continue;
         }
 
-        if let Some(anchor) = anchor_part {
-            if anchor.trim().is_empty() {
-                continue;
-            }
+        snapshot_before_rewrite(history_root, store, &document.entry.path, &document.content, "rename-heading")?;
+        writes.push((source, apply_rewrites_to_content(&document.content, &file_rewrites)));
+    }
 
-            let anchor = anchor.to_ascii_lowercase();
-            let slug_set = if target_path == document_path {
-                current_anchor_slugs.clone()
-            } else if is_text_openable_file(&target_path) {
-                match read_utf8(&target_path) {
-                    Ok(content) => heading_slugs(&content),
-                    Err(_) => HashSet::new(),
-                }
-            } else {
-                HashSet::new()
-            };
+    let self_rewrites: Vec<&PendingRewrite> = rewrites
+        .iter()
+        .filter(|rewrite| normalize_lexical_path(&rewrite.path) == document_key)
+        .collect();
+    let relinked = apply_rewrites_to_content(&document_content, &self_rewrites);
+    let updated = rename_heading_line(&relinked, &old_slug, new_heading).unwrap_or(relinked);
+    if markdown.is_some() {
+        updated_markdown = Some(updated);
+    } else if apply && updated != document_content {
+        snapshot_before_rewrite(history_root, store, &document_path, &document_content, "rename-heading")?;
+        writes.push((document_file.to_path_buf(), updated));
+    }
 
-            if !slug_set.contains(&anchor) {
-                issues.push(LinkValidationIssue {
-                    line,
-                    link: link.clone(),
-                    severity: "error".to_string(),
-                    message: "Anchor was not found in target document".to_string(),
-                });
-            }
-        }
+    if apply {
+        write_files_atomically(&writes)?;
     }
 
-    Ok(LinkValidationReport {
-        checked_external: check_external,
-        issues,
+    Ok(HeadingRenamePlan {
+        old_slug,
+        new_slug,
+        rewrites: rename_plan_dto(document_file, document_file, &rewrites).rewrites,
+        updated_markdown,
     })
 }
 
+#[tauri::command]
+pub fn rename_heading(
+    workspace_folder: String,
+    document_path: String,
+    old_heading: String,
+    new_heading: String,
+    markdown: Option<String>,
+    apply: bool,
+) -> Result<HeadingRenamePlan, AppError> {
+    let workspace_root = require_workspace_folder(&workspace_folder)?;
+    let store = load_settings_store(&settings_store_path()?)?;
+    let plan = rename_heading_in(
+        &history_dir()?,
+        &store,
+        &workspace_root,
+        Path::new(&document_path),
+        &old_heading,
+        &new_heading,
+        markdown.as_deref(),
+        apply,
+    )?;
+
+    if apply {
+        append_log(
+            "rename_heading",
+            &format!("{}#{} -> #{} ({} links)", document_path, plan.old_slug, plan.new_slug, plan.rewrites.len()),
+        );
+    }
+    Ok(plan)
+}
+
 #[tauri::command]
 pub fn save_session_state(state: SessionStateDto) -> Result<(), AppError> {
     let serialized = serde_json::to_string_pretty(&state)
@@ -1219,6 +6222,47 @@ pub fn load_session_state() -> Result<Option<SessionStateDto>, AppError> {
     Ok(Some(state))
 }
 
+#[tauri::command]
+pub fn git_status(workspace_folder: String) -> Result<GitStatusSummary, AppError> {
+    git_status_in(Path::new(&workspace_folder))
+}
+
+/// Diffs the given buffer against the version of the document committed at HEAD.
+#[tauri::command]
+pub fn git_diff_head(document_path: String, content: String) -> Result<SnapshotDiff, AppError> {
+    git_diff_head_in(Path::new(&document_path), &content)
+}
+
+#[tauri::command]
+pub fn git_stage(workspace_folder: String, paths: Vec<String>, stage: bool) -> Result<(), AppError> {
+    git_stage_in(Path::new(&workspace_folder), &paths, stage)?;
+    append_log("git_stage", &format!("{} files ({})", paths.len(), stage));
+    Ok(())
+}
+
+/// Commits only `paths` when any are given, otherwise everything that is staged.
+#[tauri::command]
+pub fn git_commit(workspace_folder: String, message: String, paths: Vec<String>) -> Result<GitCommitInfo, AppError> {
+    let commit = git_commit_in(Path::new(&workspace_folder), &message, &paths)?;
+    append_log("git_commit", &commit.id);
+    Ok(commit)
+}
+
+#[tauri::command]
+pub fn git_file_log(document_path: String, limit: Option<u32>) -> Result<Vec<GitCommitInfo>, AppError> {
+    git_file_log_in(Path::new(&document_path), limit.unwrap_or(100).max(1) as usize)
+}
+
+#[tauri::command]
+pub fn git_blame(document_path: String, content: Option<String>) -> Result<Vec<GitBlameLine>, AppError> {
+    git_blame_in(Path::new(&document_path), content.as_deref())
+}
+
+#[tauri::command]
+pub fn git_show_revision(document_path: String, revision: String) -> Result<GitRevisionContent, AppError> {
+    git_show_revision_in(Path::new(&document_path), &revision)
+}
+
 #[tauri::command]
 pub fn export_logs(destination_path: String) -> Result<(), AppError> {
     let source = app_log_path()?;
@@ -1244,9 +6288,65 @@ mod tests {
     use std::os::unix::fs as unix_fs;
     #[cfg(windows)]
     use std::os::windows::fs as windows_fs;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
     use std::thread::sleep;
     use tempfile::tempdir;
 
+    fn snapshot_record(id: &str, created_at_ms: u64) -> SnapshotRecord {
+        SnapshotRecord {
+            id: id.to_string(),
+            created_at_ms,
+            reason: "manual".to_string(),
+            size_bytes: 0,
+            file_path: String::new(),
+            content_hash: 0,
+            pinned: false,
+            blob: None,
+            name: None,
+            notes: None,
+            word_count: None,
+            words_added: None,
+            words_removed: None,
+        }
+    }
+
+    fn spawn_stub_http_server() -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+        let base = format!("http://{}", listener.local_addr().expect("addr"));
+        let served = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&served);
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else {
+                    continue;
+                };
+                let mut reader = BufReader::new(stream.try_clone().expect("clone stream"));
+                let mut request_line = String::new();
+                let _ = reader.read_line(&mut request_line);
+                let mut header = String::new();
+                while reader.read_line(&mut header).map(|read| read > 2).unwrap_or(false) {
+                    header.clear();
+                }
+
+                counter.fetch_add(1, Ordering::SeqCst);
+                let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+                let response = match path {
+                    "/ok" => "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n".to_string(),
+                    "/moved" => "HTTP/1.1 301 Moved Permanently\r\nLocation: /ok\r\nContent-Length: 0\r\n\r\n"
+                        .to_string(),
+                    _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_string(),
+                };
+                let _ = stream.write_all(response.as_bytes());
+            }
+        });
+
+        (base, served)
+    }
+
     #[test]
     fn opens_saved_document_roundtrip() {
         let temp_dir = tempdir().expect("temp dir");
@@ -1370,27 +6470,823 @@ mod tests {
     }
 
     #[test]
-    fn snapshot_retention_prunes_to_fifty() {
-        let path = "/tmp/fake.md".to_string();
-        let mut permission_denied = false;
+    fn snapshot_retention_prunes_to_per_file_cap() {
+        let temp_dir = tempdir().expect("temp dir");
+        let path = "/tmp/fake.md";
+        let retention = HistoryRetentionSettings {
+            max_snapshots_per_file: Some(50),
+            ..HistoryRetentionSettings::default()
+        };
+
         for index in 0..55 {
-            if let Err(error) =
-                create_snapshot(path.clone(), format!("content-{index}"), "manual".to_string())
-            {
-                if error.code == AppErrorCode::PermissionDenied {
-                    permission_denied = true;
-                    break;
-                }
-                panic!("snapshot: {error:?}");
+            create_snapshot_in(
+                temp_dir.path(),
+                path,
+                &format!("content-{index}"),
+                "manual",
+                &retention,
+                &HistoryStorageSettings::default(),
+            )
+            .expect("snapshot");
+        }
+
+        let index = load_history_index(temp_dir.path()).expect("index");
+        let records = &index.files[path];
+        assert_eq!(records.len(), 50);
+        assert!(records
+            .iter()
+            .all(|record| load_record_content(temp_dir.path(), &index, record).is_ok()));
+    }
+
+    #[test]
+    fn tiered_retention_thins_older_snapshots_and_keeps_pinned() {
+        let now = 400 * DAY_MS;
+        let record = |id: &str, age: u64, pinned: bool| SnapshotRecord {
+            pinned,
+            ..snapshot_record(id, now - age)
+        };
+        let records = vec![
+            record("recent-a", 5 * 60_000, false),
+            record("recent-b", 10 * 60_000, false),
+            record("hour-a", 3 * HOUR_MS + 10 * 60_000, false),
+            record("hour-b", 3 * HOUR_MS + 20 * 60_000, false),
+            record("day-a", 3 * DAY_MS + HOUR_MS, false),
+            record("day-b", 3 * DAY_MS + 2 * HOUR_MS, false),
+            record("old-pinned", 300 * DAY_MS, true),
+            record("old", 200 * DAY_MS, false),
+        ];
+        let retention = HistoryRetentionSettings {
+            weekly_for_ms: Some(90 * DAY_MS),
+            ..HistoryRetentionSettings::default()
+        };
+
+        let kept = retained_snapshot_ids(&records, &retention, now);
+        let mut kept: Vec<&str> = kept.iter().map(String::as_str).collect();
+        kept.sort();
+
+        assert_eq!(kept, vec!["day-a", "hour-a", "old-pinned", "recent-a", "recent-b"]);
+
+        for limit in [0, 1] {
+            let capped = HistoryRetentionSettings {
+                max_snapshots_per_file: Some(limit),
+                ..retention.clone()
+            };
+            let kept = retained_snapshot_ids(&records, &capped, now);
+            let mut kept: Vec<&str> = kept.iter().map(String::as_str).collect();
+            kept.sort();
+            assert_eq!(kept, vec!["old-pinned", "recent-a"], "limit {limit}");
+        }
+    }
+
+    #[test]
+    fn snapshots_are_deduplicated_delta_encoded_and_migrated() {
+        let temp_dir = tempdir().expect("temp dir");
+        let history_root = temp_dir.path();
+        let retention = HistoryRetentionSettings::default();
+        let storage = HistoryStorageSettings::default();
+        let base = "# Notes\n\n".to_string() + &"A long paragraph of text.\n".repeat(200);
+        let edited = base.replace("# Notes", "# Edited notes");
+
+        create_snapshot_in(history_root, "/docs/a.md", &base, "manual", &retention, &storage).expect("first");
+        create_snapshot_in(history_root, "/docs/a.md", &edited, "manual", &retention, &storage).expect("second");
+        create_snapshot_in(history_root, "/docs/b.md", &base, "manual", &retention, &storage).expect("copy");
+
+        let index = load_history_index(history_root).expect("index");
+        assert_eq!(index.blobs.len(), 2);
+        let edited_blob = index.files["/docs/a.md"][1].blob.clone().expect("blob");
+        assert_eq!(index.blobs[&edited_blob].depth, 1);
+        assert!(index.blobs[&edited_blob].stored_bytes < 100);
+        assert_eq!(
+            load_record_content(history_root, &index, &index.files["/docs/a.md"][1]).expect("load"),
+            edited
+        );
+
+        let legacy_dir = history_root.join("legacy");
+        fs::create_dir_all(&legacy_dir).expect("legacy dir");
+        let legacy_file = legacy_dir.join("1-1.mdsnap");
+        fs::write(&legacy_file, "legacy content").expect("legacy file");
+        let mut legacy_index = load_history_index(history_root).expect("index");
+        legacy_index.files.insert(
+            "/docs/old.md".to_string(),
+            vec![SnapshotRecord {
+                size_bytes: 14,
+                file_path: legacy_file.to_string_lossy().to_string(),
+                content_hash: hash_u64("legacy content"),
+                ..snapshot_record("1-1", 1)
+            }],
+        );
+        save_history_index(history_root, &mut legacy_index).expect("save");
+
+        let migrated = load_history_index(history_root).expect("migrate");
+        let record = &migrated.files["/docs/old.md"][0];
+        assert!(record.blob.is_some());
+        assert!(!legacy_file.exists());
+        assert_eq!(
+            load_record_content(history_root, &migrated, record).expect("load migrated"),
+            "legacy content"
+        );
+    }
+
+    #[test]
+    fn quota_pruning_frees_delta_bases() {
+        let temp_dir = tempdir().expect("temp dir");
+        let history_root = temp_dir.path();
+        let retention = HistoryRetentionSettings::default();
+        let storage = HistoryStorageSettings::default();
+        let mut chunks: Vec<String> = (0..40)
+            .map(|chunk| (0..8).map(|part| content_digest(format!("{chunk}:{part}").as_bytes())).collect())
+            .collect();
+
+        for _ in 0..5 {
+            create_snapshot_in(history_root, "/docs/a.md", &chunks.join("\n"), "manual", &retention, &storage)
+                .expect("snapshot");
+            chunks.remove(0);
+        }
+
+        let mut index = load_history_index(history_root).expect("index");
+        let records = index.files["/docs/a.md"].clone();
+        assert!(records[1..]
+            .iter()
+            .all(|record| index.blobs[record.blob.as_ref().expect("blob")].base.is_some()));
+
+        let quota = history_stored_bytes(&index) - 100;
+        enforce_history_quota(history_root, &mut index, quota).expect("quota");
+        save_history_index(history_root, &mut index).expect("save");
+
+        let index = load_history_index(history_root).expect("reload");
+        assert!(history_stored_bytes(&index) <= quota);
+        assert_eq!(index.files["/docs/a.md"].len(), 4);
+        assert!(!blob_path(history_root, records[0].blob.as_ref().expect("blob")).exists());
+        for record in &index.files["/docs/a.md"] {
+            load_record_content(history_root, &index, record).expect("readable");
+        }
+    }
+
+    #[test]
+    fn diff_texts_reports_line_and_word_changes() {
+        let old = "# Title\none\ntwo\nthree quick words\nfour\n";
+        let new = "# Title\none\ntwo\nthree slow words\nfour\nfive\n";
+        let diff = diff_texts(old, new);
+
+        assert_eq!(diff.hunks.len(), 1);
+        assert_eq!(diff.stats.lines_added, 2);
+        assert_eq!(diff.stats.lines_removed, 1);
+        assert_eq!(diff.stats.words_added, 2);
+        assert_eq!(diff.stats.words_removed, 1);
+
+        let hunk = &diff.hunks[0];
+        assert_eq!((hunk.old_start, hunk.new_start), (1, 1));
+        let deleted = hunk.lines.iter().find(|line| line.kind == "delete").expect("deleted line");
+        assert_eq!(deleted.text, "three quick words");
+        assert_eq!(deleted.old_line, Some(4));
+        assert!(deleted
+            .segments
+            .iter()
+            .any(|segment| segment.kind == "delete" && segment.text == "quick"));
+        assert!(diff_texts(old, old).hunks.is_empty());
+    }
+
+    #[test]
+    fn snapshots_can_be_labeled_and_deleted() {
+        let temp_dir = tempdir().expect("temp dir");
+        let history_root = temp_dir.path();
+        let retention = HistoryRetentionSettings::default();
+        let storage = HistoryStorageSettings::default();
+        let first = create_snapshot_in(history_root, "/docs/a.md", "draft one", "manual", &retention, &storage)
+            .expect("first");
+        let second = create_snapshot_in(history_root, "/docs/a.md", "draft two", "manual", &retention, &storage)
+            .expect("second");
+
+        let mut index = load_history_index(history_root).expect("index");
+        let record = snapshot_record_mut(&mut index, "/docs/a.md", &first.id).expect("record");
+        record.name = non_empty_label(Some("  sent to legal v2 ".to_string()));
+        record.notes = non_empty_label(Some("   ".to_string()));
+        let entry = snapshot_entry(record);
+        assert_eq!(entry.name.as_deref(), Some("sent to legal v2"));
+        assert_eq!(entry.notes, None);
+        save_history_index(history_root, &mut index).expect("save");
+
+        delete_snapshot_in(history_root, "/docs/a.md", &second.id).expect("delete");
+        let index = load_history_index(history_root).expect("index");
+        let records = &index.files["/docs/a.md"];
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].name.as_deref(), Some("sent to legal v2"));
+        assert_eq!(index.blobs.len(), 1);
+        assert!(delete_snapshot_in(history_root, "/docs/a.md", &second.id).is_err());
+    }
+
+    #[test]
+    fn history_index_survives_concurrency_corruption_and_orphans() {
+        let temp_dir = tempdir().expect("temp dir");
+        let history_root = temp_dir.path();
+
+        std::thread::scope(|scope| {
+            for worker in 0..4 {
+                scope.spawn(move || {
+                    let retention = HistoryRetentionSettings::default();
+                    let storage = HistoryStorageSettings::default();
+                    for revision in 0..5 {
+                        create_snapshot_in(
+                            history_root,
+                            &format!("/docs/{worker}.md"),
+                            &format!("worker {worker} revision {revision}"),
+                            "manual",
+                            &retention,
+                            &storage,
+                        )
+                        .expect("snapshot");
+                    }
+                });
             }
+        });
+
+        let index = load_history_index(history_root).expect("index");
+        assert_eq!(index.files.len(), 4);
+        assert!(index.files.values().all(|records| records.len() == 5));
+
+        fs::write(history_root.join("blobs.json"), "{ not json").expect("corrupt blobs");
+        fs::write(document_index_path(history_root, "/docs/0.md"), "garbage").expect("corrupt doc");
+        let orphan_blob = blob_path(history_root, &content_digest(b"orphan"));
+        atomic_write_bytes(&orphan_blob, &compress_bytes(b"Forphan").expect("compress")).expect("orphan");
+        fs::write(history_root.join("documents").join(".stale.json.1.tmp"), "").expect("temp file");
+
+        let mut index = load_history_index(history_root).expect("rebuilt index");
+        assert_eq!(index.files.len(), 4);
+        assert_eq!(index.quarantined, 1);
+        assert_eq!(index.recovered, 5);
+        let record = &index.files["/docs/0.md"][4];
+        assert_eq!(
+            load_record_content(history_root, &index, record).expect("load recovered"),
+            "worker 0 revision 4"
+        );
+        let record = &index.files["/docs/1.md"][4];
+        assert_eq!(
+            load_record_content(history_root, &index, record).expect("load"),
+            "worker 1 revision 4"
+        );
+
+        let report = clean_history_orphans(history_root, &mut index);
+        save_history_index(history_root, &mut index).expect("save");
+        assert_eq!(report.removed_blobs, 1);
+        assert_eq!(report.removed_files, 1);
+        assert_eq!(report.dropped_records, 0);
+        assert_eq!(report.recovered_records, 5);
+        assert!(!orphan_blob.exists());
+
+        let lost_blobs: Vec<PathBuf> = index.files["/docs/1.md"]
+            .iter()
+            .map(|record| blob_path(history_root, record.blob.as_ref().expect("blob")))
+            .collect();
+        fs::write(document_index_path(history_root, "/docs/1.md"), "garbage").expect("corrupt doc");
+        fs::remove_dir_all(snapshot_metadata_dir(history_root, "/docs/1.md")).expect("drop metadata");
+        let mut index = load_history_index(history_root).expect("partial index");
+        assert_eq!(index.files.len(), 3);
+        let report = clean_history_orphans(history_root, &mut index);
+        save_history_index(history_root, &mut index).expect("save");
+        assert_eq!(report.removed_blobs, 0);
+        assert!(lost_blobs.iter().all(|blob| blob.exists()));
+
+        create_snapshot_in(
+            history_root,
+            "/docs/2.md",
+            "worker 2 revision 5",
+            "manual",
+            &HistoryRetentionSettings::default(),
+            &HistoryStorageSettings::default(),
+        )
+        .expect("snapshot after rebuild");
+        let index = load_history_index(history_root).expect("index after write");
+        let records = &index.files["/docs/2.md"];
+        assert_eq!(records.len(), 6);
+        assert_eq!(
+            load_record_content(history_root, &index, &records[5]).expect("load new"),
+            "worker 2 revision 5"
+        );
+        assert!(lost_blobs.iter().all(|blob| blob.exists()));
+    }
+
+    #[test]
+    fn history_follows_canonical_paths_and_moves() {
+        let temp_dir = tempdir().expect("temp dir");
+        let history_root = temp_dir.path().join("history");
+        let docs = temp_dir.path().join("docs");
+        fs::create_dir_all(docs.join("sub")).expect("docs");
+        let original = docs.join("note.md");
+        fs::write(&original, "moved content").expect("write");
+
+        let retention = HistoryRetentionSettings::default();
+        let storage = HistoryStorageSettings::default();
+        let indirect = docs.join("sub").join("..").join("note.md");
+        create_snapshot_in(
+            &history_root,
+            &indirect.to_string_lossy(),
+            "moved content",
+            "manual",
+            &retention,
+            &storage,
+        )
+        .expect("snapshot");
+        let (index, key) = load_document_history(&history_root, &original.to_string_lossy()).expect("index");
+        assert_eq!(index.files[&key].len(), 1);
+
+        let moved = docs.join("sub").join("renamed.md");
+        fs::rename(&original, &moved).expect("move");
+        let candidates = history_candidates_in(&history_root, &moved.to_string_lossy()).expect("candidates");
+        assert_eq!(candidates.len(), 1);
+        assert!(candidates[0].matches_content);
+
+        let entries = move_document_history(&history_root, &candidates[0].path, &moved.to_string_lossy())
+            .expect("reassociate");
+        assert_eq!(entries.len(), 1);
+        let (index, key) = load_document_history(&history_root, &moved.to_string_lossy()).expect("index");
+        assert_eq!(index.files.len(), 1);
+        assert!(index.files.contains_key(&key));
+    }
+
+    #[test]
+    fn activity_timeline_buckets_edits_and_word_changes() {
+        let record = |id: &str, created_at_ms: u64, words: u64| SnapshotRecord {
+            word_count: Some(words),
+            ..snapshot_record(id, created_at_ms)
+        };
+        let mut index = HistoryIndex::default();
+        index.files.insert(
+            "/work/notes/a.md".to_string(),
+            vec![
+                record("a1", DAY_MS + HOUR_MS, 100),
+                record("a2", DAY_MS + 2 * HOUR_MS, 150),
+                record("a3", 2 * DAY_MS + HOUR_MS, 120),
+            ],
+        );
+        index
+            .files
+            .insert("/work/notes/b.md".to_string(), vec![record("b1", 2 * DAY_MS + 3 * HOUR_MS, 40)]);
+        index
+            .files
+            .insert("/elsewhere/c.md".to_string(), vec![record("c1", 2 * DAY_MS, 10)]);
+
+        let timeline = activity_timeline(
+            &index,
+            &ActivityQuery {
+                workspace_folder: Some("/work/notes".to_string()),
+                from_ms: Some(DAY_MS + 90 * 60 * 1000),
+                ..ActivityQuery::default()
+            },
+        );
+
+        assert_eq!(timeline.buckets.len(), 2);
+        assert_eq!(timeline.buckets[0].start_ms, DAY_MS);
+        assert_eq!(timeline.buckets[0].edits, 1);
+        assert_eq!(timeline.buckets[0].words_added, 50);
+        assert_eq!(timeline.buckets[1].edits, 2);
+        assert_eq!(timeline.buckets[1].words_removed, 30);
+        assert_eq!(timeline.buckets[1].words_added, 40);
+        assert_eq!(timeline.files[0].path, "/work/notes/a.md");
+        assert_eq!(timeline.files[0].edits, 2);
+        assert_eq!(timeline.files.len(), 2);
+
+        let hourly = activity_timeline(
+            &index,
+            &ActivityQuery {
+                granularity: "hour".to_string(),
+                utc_offset_minutes: 120,
+                ..ActivityQuery::default()
+            },
+        );
+        assert_eq!(hourly.buckets.len(), 5);
+        assert_eq!(hourly.buckets[0].start_ms, DAY_MS + HOUR_MS);
+
+        let temp_dir = tempdir().expect("temp dir");
+        let history_root = temp_dir.path();
+        let retention = HistoryRetentionSettings::default();
+        let storage = HistoryStorageSettings::default();
+        create_snapshot_in(history_root, "/docs/a.md", "one two three four", "manual", &retention, &storage)
+            .expect("first");
+        create_snapshot_in(history_root, "/docs/a.md", "one five six four", "manual", &retention, &storage)
+            .expect("rewrite");
+        let mut index = load_history_index(history_root).expect("index");
+        let rewritten = activity_timeline(&index, &ActivityQuery::default());
+        assert_eq!(rewritten.files[0].words_added, 4 + 2);
+        assert_eq!(rewritten.files[0].words_removed, 2);
+
+        for record in index.files.values_mut().flatten() {
+            record.words_added = None;
+            record.words_removed = None;
         }
+        assert!(backfill_word_counts(history_root, &mut index));
+        let backfilled = activity_timeline(&index, &ActivityQuery::default());
+        assert_eq!((backfilled.files[0].words_added, backfilled.files[0].words_removed), (6, 2));
+    }
+
+    #[test]
+    fn search_snapshots_finds_deleted_paragraphs() {
+        let temp_dir = tempdir().expect("temp dir");
+        let history_root = temp_dir.path();
+        let retention = HistoryRetentionSettings::default();
+        let storage = HistoryStorageSettings::default();
+        let with_paragraph = "# Plan\n\nIntro.\n\nThe Quarterly budget paragraph.\n";
+        create_snapshot_in(history_root, "/docs/plan.md", with_paragraph, "manual", &retention, &storage)
+            .expect("first");
+        create_snapshot_in(history_root, "/docs/plan.md", "# Plan\n\nIntro.\n", "manual", &retention, &storage)
+            .expect("second");
+        create_snapshot_in(history_root, "/docs/other.md", with_paragraph, "manual", &retention, &storage)
+            .expect("other");
+
+        let index = load_history_index(history_root).expect("index");
+        let tokens = vec!["quarterly".to_string(), "budget".to_string()];
+        let hits = search_snapshots_in(history_root, &index, &tokens, None, 10);
+        assert_eq!(hits.len(), 2);
+        assert!(hits.iter().all(|hit| hit.line == 5 && hit.snippet.contains("Quarterly budget")));
+
+        let scoped = search_snapshots_in(history_root, &index, &tokens, Some("/docs/plan.md"), 10);
+        assert_eq!(scoped.len(), 1);
+        assert_eq!(scoped[0].snapshot_id, index.files["/docs/plan.md"][0].id);
+    }
+
+    #[test]
+    fn history_archives_round_trip_and_merge() {
+        let source_dir = tempdir().expect("source dir");
+        let target_dir = tempdir().expect("target dir");
+        let retention = HistoryRetentionSettings::default();
+        let storage = HistoryStorageSettings::default();
+        let base = "# Shared\n\n".to_string() + &"Paragraph text.\n".repeat(50);
+        create_snapshot_in(source_dir.path(), "/a/doc.md", &base, "manual", &retention, &storage).expect("one");
+        create_snapshot_in(source_dir.path(), "/a/doc.md", &format!("{base}More.\n"), "manual", &retention, &storage)
+            .expect("two");
+        create_snapshot_in(target_dir.path(), "/b/doc.md", "Local draft", "manual", &retention, &storage)
+            .expect("local");
+
+        let archive_path = source_dir.path().join("doc.mdhistory");
+        assert_eq!(export_history_archive(source_dir.path(), "/a/doc.md", &archive_path).expect("export"), 2);
+
+        let entries = import_history_archive(target_dir.path(), &archive_path, "/b/doc.md", &storage).expect("import");
+        assert_eq!(entries.len(), 3);
+        let again = import_history_archive(target_dir.path(), &archive_path, "/b/doc.md", &storage).expect("reimport");
+        assert_eq!(again.len(), 3);
+
+        let expected = format!("{base}More.\n");
+        let index = load_history_index(target_dir.path()).expect("index");
+        let imported = index.files["/b/doc.md"]
+            .iter()
+            .find(|record| record.content_hash == hash_u64(&expected))
+            .expect("imported");
+        assert_eq!(
+            load_record_content(target_dir.path(), &index, imported).expect("content"),
+            expected
+        );
+
+        fs::write(&archive_path, b"not an archive").expect("corrupt");
+        assert!(import_history_archive(target_dir.path(), &archive_path, "/b/doc.md", &storage).is_err());
+    }
+
+    fn init_git_repository(root: &Path) -> Repository {
+        let repository = Repository::init(root).expect("init repository");
+        let mut config = repository.config().expect("config");
+        config.set_str("user.name", "Test Author").expect("user.name");
+        config.set_str("user.email", "author@example.com").expect("user.email");
+        repository
+    }
+
+    #[test]
+    fn git_status_diff_commit_and_log_round_trip() {
+        let temp_dir = tempdir().expect("temp dir");
+        let root = temp_dir.path();
+        init_git_repository(root);
+        let doc = root.join("notes.md");
+        let doc_path = doc.to_string_lossy().to_string();
+        fs::write(&doc, "# Notes\nfirst line\n").expect("write");
+
+        let status = git_status_in(root).expect("status");
+        assert_eq!(status.files.len(), 1);
+        assert_eq!(status.files[0].status, "untracked");
+        assert!(git_commit_in(root, "Empty", &[]).is_err());
+
+        let first = git_commit_in(root, "Add notes", std::slice::from_ref(&doc_path)).expect("first commit");
+        assert!(git_status_in(root).expect("status").files.is_empty());
+        assert!(git_commit_in(root, "Empty", &[]).is_err());
+
+        fs::write(&doc, "# Notes\nsecond line\n").expect("edit");
+        let status = git_status_in(root).expect("status");
+        assert_eq!(status.files[0].status, "modified");
+        assert!(status.files[0].unstaged && !status.files[0].staged);
+
+        let diff = git_diff_head_in(&doc, "# Notes\nsecond line\n").expect("diff");
+        assert_eq!((diff.stats.lines_added, diff.stats.lines_removed), (1, 1));
+
+        git_stage_in(root, std::slice::from_ref(&doc_path), true).expect("stage");
+        assert!(git_status_in(root).expect("status").files[0].staged);
+        git_stage_in(root, std::slice::from_ref(&doc_path), false).expect("unstage");
+        assert!(!git_status_in(root).expect("status").files[0].staged);
+
+        let other = root.join("other.md");
+        fs::write(&other, "staged elsewhere\n").expect("write other");
+        git_stage_in(root, &[other.to_string_lossy().to_string()], true).expect("stage other");
+        let second = git_commit_in(root, "Edit notes", std::slice::from_ref(&doc_path)).expect("second commit");
+        let repository = Repository::open(root).expect("repository");
+        let head_tree = repository.head().expect("head").peel_to_tree().expect("tree");
+        assert!(head_tree.get_name("other.md").is_none());
+        let status = git_status_in(root).expect("status");
+        assert_eq!(status.files.len(), 1);
+        assert!(status.files[0].path.ends_with("other.md") && status.files[0].staged);
+
+        let log = git_file_log_in(&doc, 10).expect("log");
+        assert_eq!(
+            log.iter().map(|commit| commit.id.clone()).collect::<Vec<_>>(),
+            vec![second.id.clone(), first.id.clone()]
+        );
+        assert_eq!(log[1].author, "Test Author");
+
+        let revision = git_show_revision_in(&doc, &first.short_id).expect("revision");
+        assert_eq!(revision.content, "# Notes\nfirst line\n");
+        assert_eq!(revision.commit.summary, "Add notes");
+    }
+
+    #[test]
+    fn git_blame_attributes_lines_and_marks_unsaved_edits() {
+        let temp_dir = tempdir().expect("temp dir");
+        let root = temp_dir.path();
+        init_git_repository(root);
+        let doc = root.join("handbook.md");
+        let doc_path = doc.to_string_lossy().to_string();
+        fs::write(&doc, "# Handbook\nBe kind.\n").expect("write");
+        let first = git_commit_in(root, "Start handbook", std::slice::from_ref(&doc_path)).expect("commit");
+        fs::write(&doc, "# Handbook\nBe kind.\nShip often.\n").expect("edit");
+        let second = git_commit_in(root, "Add shipping rule", std::slice::from_ref(&doc_path)).expect("commit");
+
+        let blame = git_blame_in(&doc, Some("# Handbook\nBe very kind.\nShip often.\n")).expect("blame");
+        assert_eq!(blame.len(), 3);
+        assert_eq!(blame[0].commit.as_ref().map(|commit| commit.id.as_str()), Some(first.id.as_str()));
+        assert!(blame[1].uncommitted && blame[1].commit.is_none());
+        let third = blame[2].commit.as_ref().expect("committed line");
+        assert_eq!(third.id, second.id);
+        assert_eq!(third.summary, "Add shipping rule");
+        assert_eq!(third.author, "Test Author");
+    }
 
-        if permission_denied {
-            return;
+    #[test]
+    fn orphaned_assets_exclude_referenced_files() {
+        let temp_dir = tempdir().expect("temp dir");
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("notes").join("assets")).expect("assets");
+        fs::write(
+            root.join("notes").join("a.md"),
+            "![Used](assets/used%20one.png)\n<img src=\"assets/html.png\">\n[file](assets/spec.pdf)\n",
+        )
+        .expect("doc");
+        for name in ["used one.png", "html.png", "spec.pdf", "stale.png", ".DS_Store"] {
+            fs::write(root.join("notes").join("assets").join(name), b"data").expect("asset");
         }
+        fs::write(root.join("notes").join("loose.png"), b"data").expect("loose");
+
+        let settings = AssetSettings::default();
+        let orphans = find_orphaned_assets_in(root, &settings).expect("orphans");
+        assert_eq!(orphans.len(), 1);
+        assert!(orphans[0].relative_path.ends_with("stale.png"));
+        assert_eq!(orphans[0].size_bytes, 4);
+
+        let referenced = root.join("notes").join("assets").join("spec.pdf");
+        assert!(orphaned_assets_to_trash(root, &settings, &[referenced.to_string_lossy().to_string()]).is_err());
+        let accepted =
+            orphaned_assets_to_trash(root, &settings, std::slice::from_ref(&orphans[0].path)).expect("accepted");
+        assert_eq!(accepted.len(), 1);
+    }
+
+    #[test]
+    fn saving_identical_asset_bytes_reuses_the_existing_file() {
+        let temp_dir = tempdir().expect("temp dir");
+        let document = temp_dir.path().join("doc.md");
+        fs::write(&document, "# Doc").expect("doc");
+        let assets_dir = temp_dir.path().join("assets");
+        fs::create_dir_all(&assets_dir).expect("assets");
+        fs::write(assets_dir.join("older.png"), b"older bytes").expect("older");
+        let settings = AssetSettings::default();
+
+        let first = save_asset_bytes(&document, "Screen Shot", "png", b"screenshot", &settings, None).expect("first");
+        let second = save_asset_bytes(&document, "image", "png", b"screenshot", &settings, None).expect("second");
+        assert!(!first.reused);
+        assert!(second.reused);
+        assert_eq!(first.path, second.path);
+
+        let existing = save_asset_bytes(&document, "copy", "png", b"older bytes", &settings, None).expect("existing");
+        assert!(existing.reused);
+        assert_eq!(existing.relative_path, "assets/older.png");
+
+        fs::write(&first.path, b"edited").expect("edit");
+        let third = save_asset_bytes(&document, "image", "png", b"screenshot", &settings, None).expect("third");
+        assert!(!third.reused);
+        assert_ne!(third.path, first.path);
+    }
+
+    #[test]
+    fn asset_settings_control_folder_and_file_names() {
+        let temp_dir = tempdir().expect("temp dir");
+        let root = temp_dir.path();
+        let document = root.join("posts").join("Hello World.md");
+        fs::create_dir_all(document.parent().expect("parent")).expect("posts");
+        fs::write(&document, "# Hello").expect("doc");
+
+        let settings = AssetSettings {
+            relative_to: "workspace".to_string(),
+            directory: "static/images/{doc}".to_string(),
+            file_name: "{doc}-{name}".to_string(),
+        };
+        let saved = save_asset_bytes(&document, "Diagram 1", "png", b"diagram", &settings, Some(root)).expect("save");
+        assert_eq!(saved.relative_path, "../static/images/hello-world/hello-world-diagram-1.png");
+        assert!(Path::new(&saved.path).exists());
+
+        let again = save_asset_bytes(&document, "Diagram 1", "png", b"other", &settings, Some(root)).expect("save");
+        assert!(again.relative_path.ends_with("hello-world-diagram-1-1.png"));
+
+        assert_eq!(utc_date(0), "1970-01-01");
+        assert_eq!(utc_date(951_782_400_000), "2000-02-29");
+        assert!(is_asset_location(Path::new("static/images/post/a.png"), &settings));
+        assert!(!is_asset_location(Path::new("posts/a.png"), &settings));
+
+        let absolute = AssetSettings {
+            directory: root.join("elsewhere").to_string_lossy().to_string(),
+            ..AssetSettings::default()
+        };
+        assert!(save_asset_bytes(&document, "x", "png", b"x", &absolute, None).is_err());
+
+        let escaping = AssetSettings {
+            directory: "../../outside".to_string(),
+            ..AssetSettings::default()
+        };
+        assert!(save_asset_bytes(&document, "x", "png", b"x", &escaping, Some(root)).is_err());
+        assert!(!root.parent().expect("parent").join("outside").exists());
+        let sibling = AssetSettings {
+            directory: "../shared".to_string(),
+            ..AssetSettings::default()
+        };
+        let saved = save_asset_bytes(&document, "x", "png", b"x", &sibling, Some(root)).expect("sibling");
+        assert!(saved.relative_path.starts_with("../shared/"));
+    }
+
+    #[test]
+    fn image_processing_downscales_converts_and_strips_exif() {
+        let source = DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(400, 200, image::Rgba([10, 120, 200, 128])));
+        let mut png = Vec::new();
+        source
+            .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+            .expect("encode png");
+
+        let disabled = ImageProcessingSettings::default();
+        assert_eq!(process_image_bytes(&png, "png", &disabled).expect("noop"), (png.clone(), "png".to_string()));
+
+        let settings = ImageProcessingSettings {
+            enabled: true,
+            max_dimension: Some(100),
+            format: "jpeg".to_string(),
+            ..ImageProcessingSettings::default()
+        };
+        let (jpeg, extension) = process_image_bytes(&png, "png", &settings).expect("process");
+        assert_eq!(extension, "jpg");
+        assert_eq!(&jpeg[..2], &[0xFF, 0xD8]);
+        let decoded = image::load_from_memory(&jpeg).expect("decode");
+        assert_eq!((decoded.width(), decoded.height()), (100, 50));
+
+        let exif = b"Exif\0\0MM\0\x2a\0\0\0\x08\0\0";
+        let mut with_exif = vec![0xFF, 0xD8, 0xFF, 0xE1];
+        with_exif.extend_from_slice(&((exif.len() + 2) as u16).to_be_bytes());
+        with_exif.extend_from_slice(exif);
+        with_exif.extend_from_slice(&jpeg[2..]);
+        let strip = ImageProcessingSettings {
+            enabled: true,
+            ..ImageProcessingSettings::default()
+        };
+        let (stripped, extension) = process_image_bytes(&with_exif, "jpg", &strip).expect("strip");
+        assert_eq!(extension, "jpg");
+        assert!(!stripped.windows(4).any(|window| window == b"Exif"));
+        assert_eq!(stripped, jpeg, "metadata is removed without re-encoding");
+        let mut malformed = vec![0xFF, 0xD8, 0xFF, 0xE1, 0x00, 0x01];
+        malformed.extend_from_slice(&jpeg[2..]);
+        assert_eq!(strip_jpeg_metadata(&malformed), None);
+        assert!(process_image_bytes(&malformed, "jpg", &strip).is_err());
+
+        let text_chunk = b"tEXtComment\0secret";
+        let mut with_text = png[..33].to_vec();
+        with_text.extend_from_slice(&((text_chunk.len() - 4) as u32).to_be_bytes());
+        with_text.extend_from_slice(text_chunk);
+        with_text.extend_from_slice(&[0, 0, 0, 0]);
+        with_text.extend_from_slice(&png[33..]);
+        let (stripped, _) = process_image_bytes(&with_text, "png", &strip).expect("strip png");
+        assert!(!stripped.windows(6).any(|window| window == b"secret"));
+        assert_eq!(stripped, png, "other chunks are copied byte for byte");
+        let mut with_gamma = png[..33].to_vec();
+        with_gamma.extend_from_slice(&[0, 0, 0, 4]);
+        with_gamma.extend_from_slice(b"gAMA\0\0\xb1\x8f");
+        with_gamma.extend_from_slice(&[0, 0, 0, 0]);
+        with_gamma.extend_from_slice(&png[33..]);
+        let (kept, _) = process_image_bytes(&with_gamma, "png", &strip).expect("keep gamma");
+        assert_eq!(kept, with_gamma);
+    }
+
+    #[test]
+    fn document_images_report_dimensions_and_cached_thumbnails() {
+        let temp_dir = tempdir().expect("temp dir");
+        let root = temp_dir.path();
+        let cache_dir = root.join("cache");
+        fs::create_dir_all(root.join("assets")).expect("assets");
+        DynamicImage::ImageRgb8(RgbImage::from_pixel(640, 320, Rgb([200, 30, 30])))
+            .save(root.join("assets").join("chart.png"))
+            .expect("png");
+        fs::write(root.join("assets").join("logo.svg"), "<svg xmlns=\"http://www.w3.org/2000/svg\"/>").expect("svg");
+        let document = root.join("doc.md");
+        let markdown = "![Chart](assets/chart.png)\n![Logo](assets/logo.svg)\n![Gone](assets/gone.png)\n[Not image](assets/chart.png)\n![[assets/chart.png]] and [[assets/chart.png]]\n";
+
+        let images = document_image_info(&document, markdown, None, Some(64), Some(&cache_dir)).expect("images");
+        assert_eq!(images.len(), 4);
+        assert_eq!((images[3].line, images[3].width), (5, Some(640)));
+        assert_eq!((images[0].width, images[0].height), (Some(640), Some(320)));
+        assert_eq!(images[0].format.as_deref(), Some("png"));
+        assert!(images[0].thumbnail.as_deref().is_some_and(|url| url.starts_with("data:image/png;base64,")));
+        assert_eq!(fs::read_dir(&cache_dir).expect("cache").count(), 1);
+        assert_eq!(images[1].format.as_deref(), Some("svg"));
+        assert!(images[1].exists && images[1].width.is_none());
+        assert!(!images[2].exists);
+
+        let cached = fs::read_dir(&cache_dir).expect("cache").next().expect("entry").expect("entry").path();
+        fs::write(&cached, b"cached").expect("overwrite cache");
+        let again = document_image_info(&document, markdown, None, Some(64), Some(&cache_dir)).expect("cached");
+        let expected = format!("data:image/png;base64,{}", BASE64_STANDARD.encode(b"cached"));
+        assert_eq!(again[0].thumbnail.as_deref(), Some(expected.as_str()));
+        let oversized = document_image_info(&document, markdown, None, Some(u32::MAX), None).expect("oversized");
+        assert!(oversized[0].thumbnail.is_some() && oversized[0].error.is_none());
+        let dimensions_only = document_image_info(&document, markdown, None, None, None).expect("dimensions");
+        assert_eq!(dimensions_only[0].width, Some(640));
+        assert!(dimensions_only[0].thumbnail.is_none());
+    }
+
+    #[test]
+    fn attachments_follow_allow_list_size_limit_and_dedupe() {
+        let temp_dir = tempdir().expect("temp dir");
+        let document = temp_dir.path().join("meeting.md");
+        fs::write(&document, "# Meeting").expect("doc");
+        let mut settings = WorkspaceSettings::default();
+        settings.attachments.max_size_bytes = 16;
+
+        let saved = save_attachment_bytes(&document, "Q3 Report.pdf", b"%PDF-1.7 data", &settings, None).expect("pdf");
+        assert!(saved.relative_path.starts_with("assets/q3-report-") && saved.relative_path.ends_with(".pdf"));
+        assert_eq!(saved.markdown, format!("[Q3 Report.pdf]({})", saved.relative_path));
+        let again = save_attachment_bytes(&document, "copy.pdf", b"%PDF-1.7 data", &settings, None).expect("dupe");
+        assert!(again.reused);
+        assert_eq!(again.path, saved.path);
+
+        assert!(save_attachment_bytes(&document, "tool.exe", b"MZ", &settings, None).is_err());
+        assert!(save_attachment_bytes(&document, "noextension", b"data", &settings, None).is_err());
+        assert!(save_attachment_bytes(&document, "big.csv", &[b'a'; 17], &settings, None).is_err());
+    }
 
-        let entries = list_snapshots(path).expect("list");
-        assert!(entries.len() <= 50);
+    #[test]
+    fn image_bytes_are_sniffed_validated_and_svg_sanitized() {
+        let mut png = Vec::new();
+        DynamicImage::ImageRgb8(RgbImage::from_pixel(8, 8, Rgb([1, 2, 3])))
+            .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+            .expect("png");
+        assert_eq!(validate_image_bytes(&png).expect("valid").1, "png");
+        assert_eq!(sniff_image_format(b"GIF89a...."), Some("gif"));
+        assert_eq!(sniff_image_format(b"RIFF\0\0\0\0WEBPVP8 "), Some("webp"));
+
+        let not_image = validate_image_bytes(b"<html>hello</html>").expect_err("html");
+        assert!(matches!(not_image.code, AppErrorCode::InvalidEncoding));
+        let truncated = validate_image_bytes(&png[..png.len() / 2]).expect_err("truncated");
+        assert!(truncated.message.contains("truncated"));
+
+        let svg = concat!(
+            "<?xml version=\"1.0\"?>\n<!DOCTYPE svg [<!ENTITY x SYSTEM \"file:///etc/passwd\">]>\n",
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" onload=\"alert(1)\">",
+            "<script>alert(2)</script>",
+            "<style>@import url(https://evil.example/a.css); rect { fill: url(#grad); }</style>",
+            "<image href=\"https://evil.example/track.png\"/>",
+            "<use xlink:href=\"#shape\"/>",
+            "<rect style=\"background: url('http://evil.example/x')\"/>",
+            "<foreignObject><div>html</div></foreignObject>",
+            "</svg>"
+        );
+        let (sanitized, extension) = validate_image_bytes(svg.as_bytes()).expect("svg");
+        let sanitized = String::from_utf8(sanitized).expect("utf8");
+        assert_eq!(extension, "svg");
+        for forbidden in ["<script", "onload", "evil.example", "DOCTYPE", "foreignObject", "@import"] {
+            assert!(!sanitized.contains(forbidden), "{forbidden} survived: {sanitized}");
+        }
+        assert!(sanitized.contains("xlink:href=\"#shape\""));
+        assert!(sanitized.contains("url(#grad)"));
+
+        assert!(validate_image_bytes(b"<svg/onload=alert(1)></svg>").is_err());
+        let animated = concat!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" xmlns:xlink=\"http://www.w3.org/1999/xlink\">",
+            "<a href=\"#x\"><animate attributeName=\"href\" values=\"javascript:alert(1)\"/></a>",
+            "<rect width=\"1\" onclick=\"alert(2)\" onLoad=\"alert(3)\">",
+            "<set attributeName=\"xlink:href\" to=\"javascript:alert(4)\"/>",
+            "<animateTransform attributeName=\"onclick\"><script>alert(5)</script></animateTransform>",
+            "</rect><style>rect { fill: u&#114;l(https://evil.example/x) }</style></svg>"
+        );
+        let (sanitized, _) = validate_image_bytes(animated.as_bytes()).expect("animated svg");
+        let sanitized = String::from_utf8(sanitized).expect("utf8");
+        for forbidden in ["javascript", "alert", "<animate", "<set", "onclick", "onLoad", "evil.example"] {
+            assert!(!sanitized.contains(forbidden), "{forbidden} survived: {sanitized}");
+        }
+        assert!(sanitized.contains("<rect width=\"1\">"));
     }
 
     #[test]
@@ -1403,6 +7299,8 @@ mod tests {
             document_path.to_string_lossy().to_string(),
             "[broken](./missing.md)".to_string(),
             false,
+            None,
+            None,
         )
         .expect("validate");
 
@@ -1410,6 +7308,339 @@ mod tests {
         assert_eq!(report.issues[0].severity, "error");
     }
 
+    #[test]
+    fn validate_links_covers_reference_wiki_and_html_links() {
+        let temp_dir = tempdir().expect("temp dir");
+        let document_path = temp_dir.path().join("doc.md");
+        fs::write(temp_dir.path().join("guide.md"), "# Setup\n").expect("write guide");
+
+        let markdown = [
+            "See [the guide][guide] and [[guide#Setup]] and [[missing page]].",
+            "Broken [ref][nowhere] and `[[ignored]]` code.",
+            "<img src=\"./assets/logo.png\"> <a href='guide.md#setup'>guide</a>",
+            "",
+            "[guide]: ./guide.md",
+            "[unused]: ./guide.md",
+        ]
+        .join("\n");
+        fs::write(&document_path, &markdown).expect("write");
+
+        let report = validate_links(document_path.to_string_lossy().to_string(), markdown, false, None, None)
+            .expect("validate");
+
+        let messages: Vec<(u32, &str, &str)> = report
+            .issues
+            .iter()
+            .map(|issue| (issue.line, issue.link.as_str(), issue.message.as_str()))
+            .collect();
+
+        assert_eq!(
+            messages,
+            vec![
+                (1, "[[missing page]]", "Target file does not exist"),
+                (2, "[ref][nowhere]", "Reference is not defined"),
+                (3, "./assets/logo.png", "Target file does not exist"),
+                (6, "[unused]: ./guide.md", "Reference definition is never used"),
+            ]
+        );
+    }
+
+    #[test]
+    fn external_checks_report_status_and_redirects_and_use_cache() {
+        let temp_dir = tempdir().expect("temp dir");
+        let document_path = temp_dir.path().join("doc.md");
+        let cache_path = temp_dir.path().join("cache.json");
+        let (base, served) = spawn_stub_http_server();
+
+        let markdown = format!("[ok]({base}/ok)\n[moved]({base}/moved)\n[gone]({base}/gone)");
+        let options = ExternalCheckOptions {
+            concurrency: 2,
+            per_host_interval_ms: 0,
+            ..ExternalCheckOptions::default()
+        };
+
+        let report =
+            validate_links_with_cache(&document_path, &markdown, None, true, &options, Some(&cache_path))
+                .expect("validate");
+
+        assert_eq!(report.issues.len(), 2);
+        assert_eq!(report.issues[0].line, 2);
+        assert_eq!(report.issues[0].status_code, Some(200));
+        assert_eq!(report.issues[0].redirect_target, Some(format!("{base}/ok")));
+        assert_eq!(report.issues[1].line, 3);
+        assert_eq!(report.issues[1].severity, "error");
+        assert_eq!(report.issues[1].status_code, Some(404));
+
+        let served_first = served.load(Ordering::SeqCst);
+        let cached =
+            validate_links_with_cache(&document_path, &markdown, None, true, &options, Some(&cache_path))
+                .expect("validate cached");
+        assert_eq!(cached.issues.len(), 2);
+        assert_eq!(served.load(Ordering::SeqCst), served_first);
+
+        let unreachable = "http://127.0.0.1:1/down".to_string();
+        let statuses = check_external_urls(std::slice::from_ref(&unreachable), &options, Some(&cache_path)).expect("unreachable");
+        assert!(statuses[&unreachable].error.is_some());
+        assert!(!load_external_link_cache(&cache_path).entries.contains_key(&unreachable));
+        assert!(load_external_link_cache(&cache_path).entries.contains_key(&format!("{base}/ok")));
+    }
+
+    #[test]
+    fn validate_links_suggests_fixes_that_apply_cleanly() {
+        let temp_dir = tempdir().expect("temp dir");
+        let docs_dir = temp_dir.path().join("docs");
+        fs::create_dir_all(&docs_dir).expect("create docs");
+        fs::write(docs_dir.join("installation.md"), "# Getting Started\n").expect("write target");
+        let document_path = temp_dir.path().join("doc.md");
+
+        let markdown = "Read [install](./docs/instalation.md) and [start](docs/installation.md#getting-startd).\n";
+        fs::write(&document_path, markdown).expect("write");
+
+        let report = validate_links_with_cache(
+            &document_path,
+            markdown,
+            Some(temp_dir.path()),
+            false,
+            &ExternalCheckOptions::default(),
+            None,
+        )
+        .expect("validate");
+
+        assert_eq!(report.issues.len(), 2);
+        let file_fix = &report.issues[0].suggestions[0];
+        assert_eq!(file_fix.kind, "file");
+        assert_eq!(file_fix.replacement, "./docs/installation.md");
+        let anchor_fix = &report.issues[1].suggestions[0];
+        assert_eq!(anchor_fix.kind, "anchor");
+        assert_eq!(anchor_fix.replacement, "docs/installation.md#getting-started");
+
+        let column = |issue: &LinkValidationIssue| issue.column.expect("column");
+        let fixed = apply_link_fix(
+            markdown.to_string(),
+            1,
+            column(&report.issues[1]),
+            report.issues[1].link.clone(),
+            anchor_fix.replacement.clone(),
+        )
+        .expect("apply anchor fix");
+        let fixed = apply_link_fix(
+            fixed,
+            1,
+            column(&report.issues[0]),
+            report.issues[0].link.clone(),
+            file_fix.replacement.clone(),
+        )
+        .expect("apply file fix");
+
+        assert_eq!(
+            fixed,
+            "Read [install](./docs/installation.md) and [start](docs/installation.md#getting-started).\n"
+        );
+
+        fs::write(docs_dir.join("A.md"), "").expect("write upper");
+        fs::write(docs_dir.join("a.md"), "").expect("write lower");
+        let markdown = "[docs/A.md](docs/A.md) and [docs/b.md](docs/b.md)\n";
+        let report = validate_links_with_cache(
+            &document_path,
+            markdown,
+            Some(temp_dir.path()),
+            false,
+            &ExternalCheckOptions::default(),
+            None,
+        )
+        .expect("validate");
+        assert_eq!(report.issues.len(), 1);
+        let issue = &report.issues[0];
+        assert_eq!(issue.link, "docs/b.md");
+        let fixed = apply_link_fix(markdown.to_string(), 1, column(issue), issue.link.clone(), "docs/a.md".to_string())
+            .expect("apply at column");
+        assert_eq!(fixed, "[docs/A.md](docs/A.md) and [docs/b.md](docs/a.md)\n");
+        assert!(apply_link_fix(markdown.to_string(), 1, 0, issue.link.clone(), "docs/a.md".to_string()).is_err());
+    }
+
+    #[test]
+    fn validate_links_decodes_destinations_and_resolves_root_links() {
+        let temp_dir = tempdir().expect("temp dir");
+        let docs_dir = temp_dir.path().join("docs");
+        fs::create_dir_all(&docs_dir).expect("create docs");
+        fs::write(docs_dir.join("My Notes.md"), "# Top\n").expect("write notes");
+        fs::write(docs_dir.join("file(1).md"), "").expect("write parens");
+        fs::write(temp_dir.path().join("index.md"), "").expect("write index");
+        let document_path = docs_dir.join("doc.md");
+
+        let markdown = [
+            "[a](My%20Notes.md) [b](<My Notes.md>) [c](My%20Notes.md?raw=1#top)",
+            "[d](/index.md) [e](./file(1).md \"Title (x)\") [f](/missing.md)",
+        ]
+        .join("\n");
+
+        let report = validate_links_with_cache(
+            &document_path,
+            &markdown,
+            Some(temp_dir.path()),
+            false,
+            &ExternalCheckOptions::default(),
+            None,
+        )
+        .expect("validate");
+
+        assert_eq!(report.issues.len(), 1);
+        assert_eq!(report.issues[0].line, 2);
+        assert_eq!(report.issues[0].link, "/missing.md");
+    }
+
+    #[test]
+    fn link_graph_reports_backlinks_orphans_and_edges() {
+        let temp_dir = tempdir().expect("temp dir");
+        let folder = temp_dir.path();
+        fs::create_dir_all(folder.join("notes")).expect("create notes");
+        fs::write(folder.join("index.md"), "Start at [design](notes/design.md).\n").expect("write index");
+        fs::write(
+            folder.join("notes/design.md"),
+            "# Design\n\nBack to [[../index]] or see ![chart](../assets/chart.png).\n",
+        )
+        .expect("write design");
+        fs::write(folder.join("notes/todo.md"), "See [design](./design.md#design).\n").expect("write todo");
+        let workspace = folder.to_string_lossy().to_string();
+
+        let backlinks = get_backlinks(
+            workspace.clone(),
+            folder.join("notes/design.md").to_string_lossy().to_string(),
+        )
+        .expect("backlinks");
+        assert_eq!(backlinks.len(), 2);
+        assert_eq!(backlinks[0].relative_path, "index.md");
+        assert_eq!(backlinks[0].context, "Start at [design](notes/design.md).");
+        assert_eq!(backlinks[1].line, 1);
+
+        let orphans = find_orphan_notes(workspace.clone()).expect("orphans");
+        assert_eq!(orphans.len(), 1);
+        assert_eq!(orphans[0].relative_path, "notes/todo.md");
+
+        let graph = export_link_graph(workspace).expect("graph");
+        assert_eq!(graph.nodes.len(), 3);
+        assert_eq!(graph.edges.len(), 3);
+        let design = graph
+            .nodes
+            .iter()
+            .find(|node| node.id == "notes/design.md")
+            .expect("design node");
+        assert_eq!((design.inbound, design.outbound), (2, 1));
+    }
+
+    #[test]
+    fn rename_document_rewrites_inbound_and_outgoing_links() {
+        let temp_dir = tempdir().expect("temp dir");
+        let folder = temp_dir.path();
+        fs::create_dir_all(folder.join("notes")).expect("create notes");
+        fs::write(folder.join("design.md"), "# Goals\n\nSee [index](index.md) and [self](design.md#goals).\n")
+            .expect("write design");
+        fs::write(
+            folder.join("index.md"),
+            "[d](design.md#goals) and [[design]] and [d2](design.md?plain=1)\n",
+        )
+        .expect("write index");
+        fs::write(
+            folder.join("notes/a.md"),
+            "![x](../design.md) [ref][d]\n\n[d]: <../design.md> \"Design\"\n",
+        )
+        .expect("write notes");
+
+        let workspace = folder.to_string_lossy().to_string();
+        let old_path = folder.join("design.md").to_string_lossy().to_string();
+        let new_path = folder.join("docs/architecture.md").to_string_lossy().to_string();
+
+        let preview = preview_document_rename(workspace.clone(), old_path.clone(), new_path.clone())
+            .expect("preview");
+        assert_eq!(preview.rewrites.len(), 7);
+        assert!(Path::new(&old_path).exists());
+
+        let history_root = folder.join(".history");
+        let rewrites = rename_document_in(
+            &history_root,
+            &SettingsStore::default(),
+            folder,
+            Path::new(&old_path),
+            Path::new(&new_path),
+        )
+        .expect("rename");
+        assert_eq!(rewrites.len(), 7);
+
+        assert!(!Path::new(&old_path).exists());
+        let index = load_history_index(&history_root).expect("history index");
+        assert!(index.files.keys().any(|key| key.ends_with("architecture.md")));
+        assert!(!index.files.keys().any(|key| key.ends_with("design.md")));
+        assert_eq!(
+            fs::read_to_string(&new_path).expect("read moved"),
+            "# Goals\n\nSee [index](../index.md) and [self](architecture.md#goals).\n"
+        );
+        assert_eq!(
+            fs::read_to_string(folder.join("index.md")).expect("read index"),
+            "[d](docs/architecture.md#goals) and [[docs/architecture]] and [d2](docs/architecture.md?plain=1)\n"
+        );
+        assert_eq!(
+            fs::read_to_string(folder.join("notes/a.md")).expect("read notes"),
+            "![x](../docs/architecture.md) [ref][d]\n\n[d]: <../docs/architecture.md> \"Design\"\n"
+        );
+    }
+
+    #[test]
+    fn rename_heading_rewrites_anchor_links() {
+        let temp_dir = tempdir().expect("temp dir");
+        let folder = temp_dir.path();
+        let guide_path = folder.join("guide.md");
+        fs::write(&guide_path, "# Old Title\n").expect("write guide");
+        fs::write(
+            folder.join("index.md"),
+            "[a](guide.md#old-title) [[guide#Old Title]] [b](guide.md#other)\n",
+        )
+        .expect("write index");
+        let buffer = "# New Title\n\nJump to [top](#old-title).\n".to_string();
+
+        let history_root = folder.join(".history");
+        let store = SettingsStore::default();
+        let plan = rename_heading_in(
+            &history_root,
+            &store,
+            folder,
+            &guide_path,
+            "## Old Title",
+            "New Title",
+            Some(&buffer),
+            true,
+        )
+        .expect("rename heading");
+
+        assert_eq!(plan.old_slug, "old-title");
+        assert_eq!(plan.new_slug, "new-title");
+        assert_eq!(plan.rewrites.len(), 3);
+        assert_eq!(
+            plan.updated_markdown.as_deref(),
+            Some("# New Title\n\nJump to [top](#new-title).\n")
+        );
+        assert_eq!(
+            fs::read_to_string(folder.join("index.md")).expect("read index"),
+            "[a](guide.md#new-title) [[guide#New Title]] [b](guide.md#other)\n"
+        );
+        assert_eq!(fs::read_to_string(&guide_path).expect("read guide"), "# Old Title\n");
+
+        fs::write(&guide_path, "# Intro\n\n## Old Title\r\n\nSee [here](#old-title).\n").expect("write guide");
+        rename_heading_in(&history_root, &store, folder, &guide_path, "Old Title", "Fresh Title", None, true)
+            .expect("rename heading on disk");
+        assert_eq!(
+            fs::read_to_string(&guide_path).expect("read guide"),
+            "# Intro\n\n## Fresh Title\r\n\nSee [here](#fresh-title).\n"
+        );
+
+        let missing = rename_heading_in(&history_root, &store, folder, &guide_path, "Old Title", "Other", None, false)
+            .expect_err("missing heading");
+        assert_eq!(missing.code, AppErrorCode::FileNotFound);
+        assert_eq!(
+            fs::read_to_string(folder.join("index.md")).expect("read index"),
+            "[a](guide.md#new-title) [[guide#New Title]] [b](guide.md#other)\n"
+        );
+    }
+
     #[test]
     fn save_and_import_image_assets() {
         let temp_dir = tempdir().expect("temp dir");
@@ -1417,7 +7648,7 @@ mod tests {
         fs::write(&document_path, "# doc").expect("write doc");
 
         let one_pixel_png =
-            "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAQAAAC1HAwCAAAAC0lEQVR42mP8/w8AAgMBgNf2N7kAAAAASUVORK5CYII=";
+            "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAAAAAA6fptVAAAACklEQVR4nGP4DwABAQEAsTj2FAAAAABJRU5ErkJggg==";
 
         let saved = save_image_asset(
             document_path.to_string_lossy().to_string(),
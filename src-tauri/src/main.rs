@@ -1,10 +1,18 @@
 mod commands;
 
 use commands::{
-    create_snapshot, export_logs, import_image_asset, list_markdown_files, list_snapshots,
-    load_recovery_draft, load_session_state, load_snapshot, open_document, save_as_document,
-    save_document, save_image_asset, save_session_state, search_workspace, store_recovery_draft,
-    validate_links, write_text_file,
+    apply_link_fix, create_snapshot, delete_snapshot, diff_snapshots, export_document_history,
+    export_link_graph, export_logs, find_history_candidates, find_orphan_notes,
+    find_orphaned_assets, get_activity_timeline, get_backlinks, get_document_images,
+    get_outgoing_links, git_blame, git_commit, git_diff_head, git_file_log, git_show_revision,
+    git_stage, git_status, import_attachment, import_document_history, import_image_asset,
+    list_markdown_files, list_snapshots, load_history_storage_settings, load_recovery_draft,
+    load_session_state, load_snapshot, load_workspace_settings, open_document,
+    preview_document_rename, reassociate_history, rename_document, rename_heading, repair_history,
+    save_as_document, save_attachment, save_document, save_history_storage_settings,
+    save_image_asset, save_session_state, save_workspace_settings, search_snapshots,
+    search_workspace, set_snapshot_pinned, store_recovery_draft, trash_orphaned_assets,
+    update_snapshot_label, validate_links, write_text_file,
 };
 use std::path::{Path, PathBuf};
 use std::sync::Mutex;
@@ -119,14 +127,49 @@ fn main() {
             search_workspace,
             save_image_asset,
             import_image_asset,
+            save_attachment,
+            import_attachment,
             create_snapshot,
             list_snapshots,
             load_snapshot,
+            diff_snapshots,
+            set_snapshot_pinned,
+            update_snapshot_label,
+            delete_snapshot,
+            get_activity_timeline,
+            search_snapshots,
+            export_document_history,
+            import_document_history,
+            find_history_candidates,
+            reassociate_history,
+            repair_history,
+            load_history_storage_settings,
+            save_history_storage_settings,
+            load_workspace_settings,
+            save_workspace_settings,
             validate_links,
+            apply_link_fix,
+            get_backlinks,
+            get_outgoing_links,
+            find_orphan_notes,
+            get_document_images,
+            find_orphaned_assets,
+            trash_orphaned_assets,
+            export_link_graph,
+            preview_document_rename,
+            rename_document,
+            rename_heading,
             save_session_state,
             load_session_state,
             write_text_file,
             export_logs,
+            git_status,
+            git_diff_head,
+            git_stage,
+            git_commit,
+            git_file_log,
+            git_blame,
+            git_show_revision,
             take_pending_open_path
         ])
         .build(tauri::generate_context!())
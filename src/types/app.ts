@@ -47,6 +47,26 @@ export interface MarkdownFileEntry {
   relativePath: string;
 }
 
+export interface ImageInfo {
+  line: number;
+  link: string;
+  path: string;
+  exists: boolean;
+  format: string | null;
+  width: number | null;
+  height: number | null;
+  sizeBytes: number;
+  thumbnail: string | null;
+  error: string | null;
+}
+
+export interface OrphanedAsset {
+  path: string;
+  relativePath: string;
+  sizeBytes: number;
+  modifiedMs: number;
+}
+
 export interface SearchHit {
   path: string;
   name: string;
@@ -58,6 +78,15 @@ export interface SearchHit {
 export interface SavedImageAsset {
   path: string;
   relativePath: string;
+  reused: boolean;
+}
+
+export interface SavedAttachment {
+  path: string;
+  relativePath: string;
+  reused: boolean;
+  sizeBytes: number;
+  markdown: string;
 }
 
 export interface SnapshotEntry {
@@ -65,13 +94,186 @@ export interface SnapshotEntry {
   createdAtMs: number;
   reason: string;
   sizeBytes: number;
+  pinned: boolean;
+  name: string | null;
+  notes: string | null;
+}
+
+export interface ActivityQuery {
+  workspaceFolder?: string | null;
+  fromMs?: number | null;
+  toMs?: number | null;
+  granularity?: "hour" | "day";
+  utcOffsetMinutes?: number;
+}
+
+export interface ActivityFileSummary {
+  path: string;
+  edits: number;
+  wordsAdded: number;
+  wordsRemoved: number;
+}
+
+export interface ActivityBucket {
+  startMs: number;
+  edits: number;
+  wordsAdded: number;
+  wordsRemoved: number;
+  files: ActivityFileSummary[];
+}
+
+export interface ActivityTimeline {
+  buckets: ActivityBucket[];
+  files: ActivityFileSummary[];
+}
+
+export interface SnapshotSearchHit {
+  path: string;
+  snapshotId: string;
+  createdAtMs: number;
+  name: string | null;
+  line: number;
+  snippet: string;
+}
+
+export interface HistoryCandidate {
+  path: string;
+  snapshotCount: number;
+  lastSnapshotMs: number;
+  matchesContent: boolean;
+}
+
+export interface HistoryRepairReport {
+  droppedRecords: number;
+  removedBlobs: number;
+  removedFiles: number;
+  quarantinedIndexes: number;
+  recoveredRecords: number;
+}
+
+export interface DiffSegment {
+  kind: "equal" | "insert" | "delete";
+  text: string;
+}
+
+export interface DiffLine {
+  kind: "equal" | "insert" | "delete";
+  oldLine: number | null;
+  newLine: number | null;
+  text: string;
+  segments: DiffSegment[];
+}
+
+export interface DiffHunk {
+  oldStart: number;
+  oldLines: number;
+  newStart: number;
+  newLines: number;
+  lines: DiffLine[];
+}
+
+export interface DiffStats {
+  linesAdded: number;
+  linesRemoved: number;
+  wordsAdded: number;
+  wordsRemoved: number;
+}
+
+export interface SnapshotDiff {
+  hunks: DiffHunk[];
+  stats: DiffStats;
+}
+
+export interface HistoryRetentionSettings {
+  keepAllForMs: number;
+  hourlyForMs: number;
+  dailyForMs: number;
+  weeklyForMs: number | null;
+  maxSnapshotsPerFile: number | null;
+  autosaveCoalesceMs: number;
+}
+
+export interface HistoryStorageSettings {
+  quotaBytes: number | null;
+  deltaEncoding: boolean;
+}
+
+export interface AssetSettings {
+  relativeTo: "document" | "workspace";
+  directory: string;
+  fileName: string;
+}
+
+export interface ImageProcessingSettings {
+  enabled: boolean;
+  maxDimension: number | null;
+  format: "keep" | "png" | "jpeg" | "webp";
+  quality: number;
+  stripMetadata: boolean;
+}
+
+export interface AttachmentSettings {
+  allowedExtensions: string[];
+  maxSizeBytes: number;
+}
+
+export interface WorkspaceSettings {
+  history: HistoryRetentionSettings;
+  assets: AssetSettings;
+  images: ImageProcessingSettings;
+  attachments: AttachmentSettings;
+}
+
+export interface GitFileStatus {
+  path: string;
+  relativePath: string;
+  status: "modified" | "added" | "deleted" | "renamed" | "untracked" | "conflicted";
+  staged: boolean;
+  unstaged: boolean;
+}
+
+export interface GitStatusSummary {
+  repositoryRoot: string;
+  branch: string | null;
+  files: GitFileStatus[];
+}
+
+export interface GitCommitInfo {
+  id: string;
+  shortId: string;
+  summary: string;
+  author: string;
+  email: string;
+  timeMs: number;
+}
+
+export interface GitBlameLine {
+  line: number;
+  commit: GitCommitInfo | null;
+  uncommitted: boolean;
+}
+
+export interface GitRevisionContent {
+  path: string;
+  commit: GitCommitInfo;
+  content: string;
 }
 
 export interface LinkValidationIssue {
   line: number;
+  column: number | null;
   link: string;
   severity: "error" | "warning";
   message: string;
+  statusCode: number | null;
+  redirectTarget: string | null;
+  suggestions: LinkFixSuggestion[];
+}
+
+export interface LinkFixSuggestion {
+  kind: "file" | "anchor" | "case";
+  replacement: string;
+  description: string;
 }
 
 export interface LinkValidationReport {
@@ -79,6 +281,69 @@ export interface LinkValidationReport {
   issues: LinkValidationIssue[];
 }
 
+export interface ExternalCheckOptions {
+  concurrency?: number;
+  perHostIntervalMs?: number;
+  cacheTtlMs?: number;
+  timeoutMs?: number;
+}
+
+export interface BacklinkEntry {
+  path: string;
+  relativePath: string;
+  line: number;
+  link: string;
+  context: string;
+}
+
+export interface OutgoingLinkEntry {
+  line: number;
+  link: string;
+  kind: "inline" | "image" | "autolink" | "reference" | "wiki" | "htmlImage" | "htmlAnchor";
+  targetPath: string | null;
+  exists: boolean;
+}
+
+export interface LinkGraphNode {
+  id: string;
+  path: string;
+  name: string;
+  inbound: number;
+  outbound: number;
+}
+
+export interface LinkGraphEdge {
+  source: string;
+  target: string;
+  count: number;
+}
+
+export interface LinkGraph {
+  nodes: LinkGraphNode[];
+  edges: LinkGraphEdge[];
+}
+
+export interface LinkRewrite {
+  path: string;
+  relativePath: string;
+  line: number;
+  oldLink: string;
+  newLink: string;
+}
+
+export interface DocumentRenamePlan {
+  oldPath: string;
+  newPath: string;
+  rewrites: LinkRewrite[];
+}
+
+export interface HeadingRenamePlan {
+  oldSlug: string;
+  newSlug: string;
+  rewrites: LinkRewrite[];
+  updatedMarkdown: string | null;
+}
+
 export type ExportProfile = "clean-markdown" | "html" | "pdf-print";
 
 export interface SessionState {